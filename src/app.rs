@@ -1,12 +1,53 @@
 use crate::{
-    input_manager::{InputManager, KeyCode},
-    renderer::Renderer,
-    timer::Timer,
+    camera::CameraMode,
+    camera_path::CameraPath,
+    input_manager::{InputManager, KeyCode, MouseButton},
+    post_process::PostEffect,
+    profiler::{TraceLayer, TraceRecorder},
+    renderer::{Renderer, SkyMode},
+    timer::{Repeat, TimerHandle, TimerScheduler},
     timing::Timing,
 };
-use log::info;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use winit::{event_loop, window};
 
+/// where F1 writes the captured trace to
+const TRACE_PATH: &str = "trace.json";
+
+/// R records a camera path to this file, G plays it back
+const CAMERA_PATH: &str = "camera_path.txt";
+
+/// E exports the recorded camera path resampled to this many keyframes per second, to render
+/// videos frame by frame
+const CAMERA_PATH_EXPORT: &str = "camera_path_export.txt";
+const CAMERA_PATH_EXPORT_RATE: f64 = 60.0;
+
+/// a capture started with F1 stops on its own after this long, unless F1 is pressed again
+const TRACE_CAPTURE_DURATION: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// L loads the color grading lut from this file, see `Texture::from_lut` for the layout. it is
+/// loaded on startup as well if it exists.
+const COLOR_GRADING_LUT: &str = "color_grading_lut.png";
+
+/// F4 fades the fog in or out over this long
+const FOG_FADE_DURATION: std::time::Duration = std::time::Duration::from_millis(800);
+
+/// up and down double and halve the time scale up to this factor in either direction
+const MAX_TIME_SCALE: f32 = 8.0;
+
+/// Y cycles through these simulation rates in steps per second. low rates make the
+/// interpolation between steps visible.
+const FIXED_RATES: [f32; 3] = [60.0, 120.0, 20.0];
+
+/// how often the frame statistics are logged
+const FRAME_STATS_LOG_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// events queued by timers of the app's scheduler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AppEvent {
+    LogFrameStats,
+}
+
 pub struct App {
     event_loop: Option<event_loop::EventLoop<()>>,
     window: window::Window,
@@ -14,35 +55,61 @@ pub struct App {
     input_manager: InputManager,
     should_exit: bool,
     timing: Timing,
+    trace_recorder: TraceRecorder,
+    /// advanced by the real time, so its timers fire on time while the game is paused or slowed
+    /// down
+    scheduler: TimerScheduler<AppEvent>,
+    stop_trace_capture: Option<TimerHandle>,
+    cursor_grabbed: bool,
 }
 
 impl App {
     pub async fn new() -> anyhow::Result<Self> {
         let filter = tracing_subscriber::filter::EnvFilter::new("warn,grass=trace");
-        tracing_subscriber::fmt::fmt()
-            .with_env_filter(filter)
+        let trace_recorder = TraceRecorder::new();
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer())
+            .with(TraceLayer::new(trace_recorder.clone()))
             .init();
 
         let event_loop = event_loop::EventLoop::new();
         let window = window::WindowBuilder::new().build(&event_loop)?;
         let event_loop = Some(event_loop);
 
-        let renderer = Renderer::new(&window).await?;
+        let mut renderer = Renderer::new(&window).await?;
+        renderer
+            .profiler_mut()
+            .set_recorder(Some(trace_recorder.clone()));
 
         let input_manager = InputManager::new();
         let timing = Timing::new();
-        Ok(Self {
+        let mut scheduler = TimerScheduler::new();
+        scheduler.schedule_event(
+            FRAME_STATS_LOG_INTERVAL,
+            Repeat::Forever,
+            AppEvent::LogFrameStats,
+        );
+        let mut app = Self {
             event_loop,
             window,
             renderer,
             input_manager,
             should_exit: false,
             timing,
-        })
+            trace_recorder,
+            scheduler,
+            stop_trace_capture: None,
+            cursor_grabbed: false,
+        };
+        if std::path::Path::new(COLOR_GRADING_LUT).exists() {
+            app.load_color_grading_lut()
+        }
+        Ok(app)
     }
 
     pub fn run(mut self) {
-        let event_loop = std::mem::replace(&mut self.event_loop, None).unwrap();
+        let event_loop = self.event_loop.take().unwrap();
 
         event_loop.run(move |event, _, control_flow| {
             control_flow.set_poll();
@@ -68,6 +135,9 @@ impl App {
                 }
                 self.input_manager.update(event)
             }
+            winit::event::Event::DeviceEvent { event, .. } => {
+                self.input_manager.update_device(event)
+            }
             winit::event::Event::MainEventsCleared => {
                 self.update();
                 self.window.request_redraw()
@@ -78,24 +148,320 @@ impl App {
     }
 
     fn update(&mut self) {
+        let _span = tracing::trace_span!("update").entered();
         if self.input_manager.is_key_pressed(KeyCode::Escape) {
             self.should_exit = true
         }
         if self.input_manager.is_key_just_pressed(KeyCode::F11) {
             self.window.set_maximized(!self.window.is_maximized())
         }
+        if self.input_manager.is_key_just_pressed(KeyCode::F1) {
+            self.toggle_trace_capture()
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::P) {
+            self.timing.set_paused(!self.timing.paused())
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::Up) {
+            self.timing
+                .set_time_scale((self.timing.time_scale() * 2.0).min(MAX_TIME_SCALE))
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::Down) {
+            self.timing
+                .set_time_scale((self.timing.time_scale() * 0.5).max(1.0 / MAX_TIME_SCALE))
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::Y) {
+            let fixed_timestep = self.timing.fixed_timestep_mut();
+            let current = FIXED_RATES
+                .iter()
+                .position(|rate| (rate - fixed_timestep.rate()).abs() < 0.5);
+            let rate = FIXED_RATES[current.map_or(0, |current| (current + 1) % FIXED_RATES.len())];
+            fixed_timestep.set_rate(rate);
+            tracing::info!("simulating {} steps per second", rate)
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::F2) {
+            self.renderer.set_msaa(self.renderer.msaa().next())
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::F3) {
+            self.renderer.set_sky_mode(match self.renderer.sky_mode() {
+                SkyMode::Skybox => SkyMode::Procedural,
+                SkyMode::Procedural => SkyMode::Skybox,
+            })
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::F4) {
+            let fog = self.renderer.fog_mut();
+            fog.fade(!fog.enabled, FOG_FADE_DURATION)
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::F5) {
+            let hdr = self.renderer.hdr_mut();
+            hdr.tonemapper = hdr.tonemapper.next()
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::F6) {
+            let hdr = self.renderer.hdr_mut();
+            hdr.set_auto_exposure(!hdr.auto_exposure())
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::C) {
+            self.renderer
+                .set_camera_mode(self.renderer.camera_mode().next())
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::V) {
+            self.renderer
+                .set_projection(self.renderer.projection().next())
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::X) {
+            self.renderer
+                .set_infinite_far(!self.renderer.infinite_far())
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::Z) {
+            self.renderer.set_reversed_z(!self.renderer.reversed_z())
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::R) {
+            self.toggle_camera_path_recording()
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::G) {
+            self.toggle_camera_path_playback()
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::E) {
+            self.export_camera_path()
+        }
+        // left dragging rotates the orbit camera instead
+        if self
+            .input_manager
+            .is_mouse_button_just_pressed(MouseButton::Left)
+            && self.renderer.camera_mode() != CameraMode::Orbit
+        {
+            match self.renderer.pick(self.input_manager.mouse_position()) {
+                Some(hit) => tracing::info!(
+                    "picked {:?} at {:?} with normal {:?}",
+                    hit.object,
+                    hit.point,
+                    hit.normal
+                ),
+                None => tracing::info!("picked nothing"),
+            }
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::M) {
+            let next = (self.renderer.diffuse_image() + 1) % self.renderer.diffuse_image_count();
+            if let Err(err) = self.renderer.set_diffuse_image(next) {
+                tracing::error!("failed to switch the diffuse image: {}", err)
+            }
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::O) {
+            let ssao = self.renderer.ssao_mut();
+            ssao.enabled = !ssao.enabled
+        }
+        for (key_code, effect) in [
+            (KeyCode::F7, PostEffect::Bloom),
+            (KeyCode::F8, PostEffect::ColorGrading),
+            (KeyCode::F9, PostEffect::Vignette),
+            (KeyCode::F10, PostEffect::FilmGrain),
+            (KeyCode::F12, PostEffect::Sharpen),
+        ] {
+            if self.input_manager.is_key_just_pressed(key_code) {
+                self.renderer
+                    .post_process_mut()
+                    .effects_mut()
+                    .toggle(effect)
+            }
+        }
 
+        if self.input_manager.is_key_just_pressed(KeyCode::K) {
+            self.rotate_post_effects()
+        }
+        if self.input_manager.is_key_just_pressed(KeyCode::L) {
+            self.load_color_grading_lut()
+        }
+
+        self.scheduler.update(self.timing.real_time_delta());
+        let events: Vec<AppEvent> = self.scheduler.drain_events().collect();
+        for event in events {
+            match event {
+                AppEvent::LogFrameStats => self.log_frame_stats(),
+            }
+        }
+
+        for _ in 0..self.timing.fixed_steps() {
+            self.renderer
+                .fixed_update(&self.input_manager, &self.timing);
+        }
         self.renderer.update(&self.input_manager, &self.timing);
+        self.grab_cursor(self.renderer.camera_grabs_cursor());
 
-        self.window
-            .set_title(&format!("FPS: {}", self.timing.fps()));
+        let frame_stats = self.timing.frame_stats();
+        let mut title = format!(
+            "FPS: {} | 1% low: {:.0} | frame: {:.2}ms (max {:.2}ms)",
+            self.timing.fps(),
+            frame_stats.one_percent_low().unwrap_or(0.0),
+            frame_stats.average().unwrap_or_default().as_secs_f64() * 1000.0,
+            frame_stats.max().unwrap_or_default().as_secs_f64() * 1000.0,
+        );
+        let gpu_scopes = self.renderer.profiler().last_frame();
+        if let Some(slowest) = gpu_scopes.iter().max_by_key(|scope| scope.duration) {
+            let total: std::time::Duration = gpu_scopes.iter().map(|scope| scope.duration).sum();
+            title += &format!(
+                " | gpu: {:.2}ms (slowest {} {:.2}ms)",
+                total.as_secs_f64() * 1000.0,
+                slowest.label,
+                slowest.duration.as_secs_f64() * 1000.0
+            );
+        }
+        if let Some(remaining) = self
+            .stop_trace_capture
+            .and_then(|handle| self.scheduler.remaining(handle))
+        {
+            title += &format!(" | capturing trace ({}s left)", remaining.as_secs() + 1);
+        }
+        self.window.set_title(&title);
 
         self.input_manager.clear();
         self.timing.update();
     }
 
     fn draw(&mut self) -> anyhow::Result<()> {
+        let _span = tracing::trace_span!("draw").entered();
         self.renderer.render()?;
         Ok(())
     }
+
+    /// confines and hides the cursor while looking around, so it can't leave the window
+    fn grab_cursor(&mut self, grab: bool) {
+        if grab == self.cursor_grabbed {
+            return;
+        }
+        self.cursor_grabbed = grab;
+        let result = if grab {
+            // not every platform supports both modes
+            self.window
+                .set_cursor_grab(window::CursorGrabMode::Locked)
+                .or_else(|_| {
+                    self.window
+                        .set_cursor_grab(window::CursorGrabMode::Confined)
+                })
+        } else {
+            self.window.set_cursor_grab(window::CursorGrabMode::None)
+        };
+        if let Err(err) = result {
+            tracing::warn!("failed to grab the cursor: {}", err)
+        }
+        self.window.set_cursor_visible(!grab)
+    }
+
+    /// the second press saves the recorded path
+    fn toggle_camera_path_recording(&mut self) {
+        let Some(path) = self.renderer.stop_recording_camera_path() else {
+            self.renderer.start_recording_camera_path();
+            tracing::info!("started recording a camera path");
+            return;
+        };
+        match path.save(CAMERA_PATH) {
+            Ok(()) => tracing::info!(
+                "wrote {} camera keyframes to {}",
+                path.keyframes().len(),
+                CAMERA_PATH
+            ),
+            Err(err) => tracing::error!("failed to write camera path to {}: {}", CAMERA_PATH, err),
+        }
+    }
+
+    /// plays the saved path in a loop, the second press hands the camera back
+    fn toggle_camera_path_playback(&mut self) {
+        if self.renderer.playing_camera_path() {
+            self.renderer.stop_camera_path();
+            return;
+        }
+        match CameraPath::load(CAMERA_PATH) {
+            Ok(path) if path.is_empty() => tracing::warn!("{} has no keyframes", CAMERA_PATH),
+            Ok(path) => self.renderer.play_camera_path(path, true),
+            Err(err) => tracing::error!("failed to load camera path from {}: {}", CAMERA_PATH, err),
+        }
+    }
+
+    fn export_camera_path(&mut self) {
+        let result = CameraPath::load(CAMERA_PATH).and_then(|path| {
+            path.resample(CAMERA_PATH_EXPORT_RATE)?
+                .save(CAMERA_PATH_EXPORT)
+        });
+        match result {
+            Ok(()) => tracing::info!("exported camera path to {}", CAMERA_PATH_EXPORT),
+            Err(err) => tracing::error!("failed to export camera path: {}", err),
+        }
+    }
+
+    /// moves the first effect running after tonemapping behind the others, to compare the
+    /// orders they can run in
+    fn rotate_post_effects(&mut self) {
+        let effects = self.renderer.post_process_mut().effects_mut();
+        let mut order: Vec<_> = effects
+            .iter()
+            .map(|(effect, _)| effect)
+            .filter(|effect| !effect.before_tonemapping())
+            .collect();
+        if order.is_empty() {
+            return;
+        }
+        order.rotate_left(1);
+        effects.set_order(&order);
+        tracing::info!("post effects run in the order {:?}", order)
+    }
+
+    fn load_color_grading_lut(&mut self) {
+        let result = image::open(COLOR_GRADING_LUT)
+            .map_err(anyhow::Error::from)
+            .and_then(|image| self.renderer.set_color_grading_lut(&image));
+        match result {
+            Ok(()) => tracing::info!("loaded color grading lut from {}", COLOR_GRADING_LUT),
+            Err(err) => tracing::error!(
+                "failed to load color grading lut from {}: {}",
+                COLOR_GRADING_LUT,
+                err
+            ),
+        }
+    }
+
+    fn log_frame_stats(&self) {
+        let frame_stats = self.timing.frame_stats();
+        let ms = |frame_time: Option<std::time::Duration>| {
+            frame_time.unwrap_or_default().as_secs_f64() * 1000.0
+        };
+        tracing::info!(
+            "frame times over the last {} frames: min {:.2}ms, median {:.2}ms, 99th percentile {:.2}ms, 1% low {:.0} fps, 0.1% low {:.0} fps, {} hitches so far",
+            frame_stats.len(),
+            ms(frame_stats.min()),
+            ms(frame_stats.percentile(50.0)),
+            ms(frame_stats.percentile(99.0)),
+            frame_stats.one_percent_low().unwrap_or(0.0),
+            frame_stats.point_one_percent_low().unwrap_or(0.0),
+            frame_stats.hitch_count()
+        )
+    }
+
+    /// the first press starts capturing cpu and gpu scopes, the second (or the scheduled stop
+    /// after `TRACE_CAPTURE_DURATION`) writes them to a chrome trace file
+    fn toggle_trace_capture(&mut self) {
+        if !self.trace_recorder.is_capturing() {
+            if !self.renderer.profiler().enabled() {
+                tracing::warn!("gpu profiling is not supported, only cpu scopes are captured")
+            }
+            self.trace_recorder.start_capture();
+            let recorder = self.trace_recorder.clone();
+            self.stop_trace_capture = Some(self.scheduler.schedule_callback(
+                TRACE_CAPTURE_DURATION,
+                Repeat::Once,
+                move || finish_trace_capture(&recorder),
+            ));
+            tracing::info!("started capturing a trace");
+            return;
+        }
+        if let Some(handle) = self.stop_trace_capture.take() {
+            self.scheduler.cancel(handle);
+        }
+        finish_trace_capture(&self.trace_recorder)
+    }
+}
+
+fn finish_trace_capture(recorder: &TraceRecorder) {
+    recorder.stop_capture();
+    match recorder.write_chrome_trace(TRACE_PATH) {
+        Ok(()) => tracing::info!("wrote trace to {}", TRACE_PATH),
+        Err(err) => tracing::error!("failed to write trace to {}: {}", TRACE_PATH, err),
+    }
 }
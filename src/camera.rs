@@ -1,8 +1,13 @@
-use cgmath::{perspective, Deg, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use std::time::Duration;
+
+use cgmath::{
+    ortho, perspective, Angle, Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix,
+    Vector3, VectorSpace,
+};
 
 use crate::{
-    input_manager::{InputManager, KeyCode},
-    timing::Timing,
+    input_manager::{InputManager, KeyCode, MouseButton},
+    terrain::HeightField,
 };
 
 #[rustfmt::skip]
@@ -13,6 +18,39 @@ const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+/// maps depth 0 to 1 and 1 to 0, spreading float precision evenly over the distance
+#[rustfmt::skip]
+const REVERSE_Z_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, -1.0, 0.0,
+    0.0, 0.0, 1.0, 1.0,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// uses `Camera::fovy`
+    Perspective,
+    /// looks along the view direction without foreshortening, `height` world units tall
+    Orthographic { height: f32 },
+    /// orthographic map looking straight down on the eye, the view direction points up on
+    /// screen
+    TopDown { height: f32 },
+}
+
+impl Projection {
+    pub fn next(&self) -> Self {
+        match self {
+            Projection::Perspective => Projection::Orthographic { height: 20.0 },
+            Projection::Orthographic { height } => Projection::TopDown {
+                height: *height * 2.0,
+            },
+            Projection::TopDown { .. } => Projection::Perspective,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Camera {
     pub eye: Point3<f32>,
     pub direction: Vector3<f32>,
@@ -21,14 +59,107 @@ pub struct Camera {
     pub aspect: f32,
     pub near: f32,
     pub far: f32,
+    pub projection: Projection,
+    /// pushes the far plane of a perspective projection to infinity, `far` then only limits
+    /// the fog
+    pub infinite_far: bool,
+    /// stores depth from 1 at the near plane to 0 at the far plane. depth tests and clears
+    /// have to be flipped to match, see `far_depth`.
+    pub reversed_z: bool,
 }
 
 impl Camera {
     pub fn build_view_projection_matrix(&self) -> Matrix4<f32> {
-        // let view = Matrix4::look_to_rh(self.eye, self.target, self.up);
-        let view = Matrix4::look_to_rh(self.eye, self.direction, self.up);
-        let projection = perspective(Deg(self.fovy), self.aspect, self.near, self.far);
-        projection * view * OPENGL_TO_WGPU_MATRIX
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective | Projection::Orthographic { .. } => {
+                Matrix4::look_to_rh(self.eye, self.direction, self.up)
+            }
+            Projection::TopDown { .. } => {
+                let forward = Vector3::new(self.direction.x, 0.0, self.direction.z);
+                let forward = if forward.magnitude2() > f32::EPSILON {
+                    forward.normalize()
+                } else {
+                    -Vector3::unit_z()
+                };
+                Matrix4::look_to_rh(self.eye, -Vector3::unit_y(), forward)
+            }
+        }
+    }
+
+    /// projects into wgpu's clip space with a depth range of [0, 1]
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        let projection = match self.projection {
+            Projection::Perspective if self.infinite_far => {
+                infinite_perspective(Deg(self.fovy), self.aspect, self.near)
+            }
+            Projection::Perspective => {
+                // remaps the depth range from opengl's [-1, 1] to wgpu's [0, 1]
+                OPENGL_TO_WGPU_MATRIX
+                    * perspective(Deg(self.fovy), self.aspect, self.near, self.far)
+            }
+            Projection::Orthographic { height } => {
+                let (width, height) = (height * self.aspect / 2.0, height / 2.0);
+                OPENGL_TO_WGPU_MATRIX * ortho(-width, width, -height, height, self.near, self.far)
+            }
+            Projection::TopDown { height } => {
+                // the map shows what is above the eye as well
+                let (width, height) = (height * self.aspect / 2.0, height / 2.0);
+                OPENGL_TO_WGPU_MATRIX * ortho(-width, width, -height, height, -self.far, self.far)
+            }
+        };
+        if self.reversed_z {
+            REVERSE_Z_MATRIX * projection
+        } else {
+            projection
+        }
+    }
+
+    /// depth the far plane and the sky end up at, which is also what depth is cleared to
+    pub fn far_depth(&self) -> f32 {
+        if self.reversed_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// blends the position, direction and field of view towards `next`, everything else is
+    /// taken from `next`
+    pub fn interpolate(&self, next: &Camera, alpha: f32) -> Camera {
+        let direction = self.direction.lerp(next.direction, alpha);
+        Camera {
+            eye: Point3::from_vec(self.eye.to_vec().lerp(next.eye.to_vec(), alpha)),
+            direction: if direction.magnitude2() > f32::EPSILON {
+                direction.normalize()
+            } else {
+                next.direction
+            },
+            fovy: self.fovy + (next.fovy - self.fovy) * alpha,
+            ..next.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+impl Camera {
+    /// perspective camera at the origin looking along x, tests change what they need
+    pub fn test_default() -> Self {
+        Self {
+            eye: Point3::new(0.0, 0.0, 0.0),
+            direction: Vector3::unit_x(),
+            up: Vector3::unit_y(),
+            fovy: 45.0,
+            aspect: 1.0,
+            near: 0.1,
+            far: 100.0,
+            projection: Projection::Perspective,
+            infinite_far: false,
+            reversed_z: false,
+        }
     }
 }
 
@@ -36,47 +167,521 @@ impl Camera {
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     view_projection_matrix: [[f32; 4]; 4],
+    inverse_view_projection_matrix: [[f32; 4]; 4],
+    // w is the depth of the far plane, 0.0 with reversed z
+    position: [f32; 4],
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
             view_projection_matrix: Matrix4::identity().into(),
+            inverse_view_projection_matrix: Matrix4::identity().into(),
+            position: [0.0; 4],
         }
     }
 
     pub fn update(&mut self, camera: &Camera) {
-        self.view_projection_matrix = camera.build_view_projection_matrix().into()
+        let view_projection_matrix = camera.build_view_projection_matrix();
+        self.view_projection_matrix = view_projection_matrix.into();
+        self.inverse_view_projection_matrix = view_projection_matrix
+            .invert()
+            .unwrap_or_else(Matrix4::identity)
+            .into();
+        self.position = camera.eye.to_vec().extend(camera.far_depth()).into()
+    }
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+/// which controller moves the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    Fly,
+    Orbit,
+    Walk,
+}
+
+impl CameraMode {
+    pub fn next(&self) -> Self {
+        match self {
+            CameraMode::Fly => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Walk,
+            CameraMode::Walk => CameraMode::Fly,
+        }
+    }
+}
+
+/// how far up or down the camera can look, just short of straight up so the view matrix stays
+/// well defined
+const MAX_PITCH: Rad<f32> = Rad(89.0 * std::f32::consts::PI / 180.0);
+
+/// free flying camera. WASD moves relative to the view direction, space and shift move up and
+/// down, holding the right mouse button looks around.
 pub struct CameraController {
     speed: f32,
+    /// radians per pixel of mouse movement
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    looking: bool,
 }
 
 impl CameraController {
     pub fn new(speed: f32) -> Self {
-        Self { speed }
+        Self {
+            speed,
+            sensitivity: 0.003,
+            invert_y: false,
+            looking: false,
+        }
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera, input: &InputManager, timing: &Timing) {
+    /// whether the mouse currently rotates the camera, the cursor should be grabbed and hidden
+    /// meanwhile
+    pub fn looking(&self) -> bool {
+        self.looking
+    }
+
+    /// rotates the camera by the mouse movement of this frame. has to be called once per frame,
+    /// unlike `update_camera` which may run several times.
+    pub fn handle_input(&mut self, camera: &mut Camera, input: &InputManager) {
+        self.looking = input.is_mouse_button_pressed(MouseButton::Right);
+        if !self.looking {
+            return;
+        }
+        let (dx, dy) = input.mouse_motion();
+        let dy = if self.invert_y { -dy } else { dy };
+        let (yaw, pitch) = yaw_pitch(camera.direction);
+        camera.direction = direction_from_yaw_pitch(
+            yaw + Rad(dx * self.sensitivity),
+            pitch - Rad(dy * self.sensitivity),
+        );
+    }
+
+    pub fn update_camera(
+        &mut self,
+        camera: &mut Camera,
+        input: &InputManager,
+        time_delta: Duration,
+    ) {
+        let distance = self.speed * time_delta.as_secs_f32();
+        let right = camera.direction.cross(camera.up);
+        let right = if right.magnitude2() > f32::EPSILON {
+            right.normalize()
+        } else {
+            Vector3::unit_x()
+        };
         if input.is_key_pressed(KeyCode::W) {
-            camera.eye += camera.direction * self.speed * timing.time_delta().as_secs_f32();
+            camera.eye += camera.direction * distance;
         }
         if input.is_key_pressed(KeyCode::S) {
-            camera.eye -= camera.direction * self.speed * timing.time_delta().as_secs_f32();
+            camera.eye -= camera.direction * distance;
         }
         if input.is_key_pressed(KeyCode::A) {
-            camera.eye.x += self.speed * timing.time_delta().as_secs_f32();
+            camera.eye -= right * distance;
         }
         if input.is_key_pressed(KeyCode::D) {
-            camera.eye.x -= self.speed * timing.time_delta().as_secs_f32();
+            camera.eye += right * distance;
         }
         if input.is_key_pressed(KeyCode::Space) {
-            camera.eye.y += self.speed * timing.time_delta().as_secs_f32();
+            camera.eye.y += distance;
         }
         if input.is_key_pressed(KeyCode::LShift) {
-            camera.eye.y -= self.speed * timing.time_delta().as_secs_f32();
+            camera.eye.y -= distance;
+        }
+    }
+}
+
+/// wheel steps scale the orbit distance by this factor
+const ZOOM_FACTOR: f32 = 1.15;
+
+/// orbits a target point. dragging with the left mouse button rotates, the wheel zooms and
+/// dragging with the middle button pans the target. input only moves the goal, the camera
+/// follows it with exponential damping so motion stays smooth.
+pub struct OrbitCameraController {
+    target: Point3<f32>,
+    yaw: Rad<f32>,
+    pitch: Rad<f32>,
+    distance: f32,
+    goal_target: Point3<f32>,
+    goal_yaw: Rad<f32>,
+    goal_pitch: Rad<f32>,
+    goal_distance: f32,
+    /// radians per pixel of mouse movement
+    pub sensitivity: f32,
+    /// fraction of the distance panned per pixel
+    pub pan_speed: f32,
+    /// how quickly the camera catches up with the goal, higher is stiffer
+    pub damping: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    dragging: bool,
+}
+
+impl OrbitCameraController {
+    pub fn new(target: Point3<f32>, distance: f32) -> Self {
+        Self {
+            target,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            distance,
+            goal_target: target,
+            goal_yaw: Rad(0.0),
+            goal_pitch: Rad(0.0),
+            goal_distance: distance,
+            sensitivity: 0.005,
+            pan_speed: 0.002,
+            damping: 12.0,
+            min_distance: 0.5,
+            max_distance: 200.0,
+            dragging: false,
+        }
+    }
+
+    /// starts orbiting the point `distance` in front of the camera without moving it
+    pub fn focus(&mut self, camera: &Camera, distance: f32) {
+        let distance = distance.clamp(self.min_distance, self.max_distance);
+        let (yaw, pitch) = yaw_pitch(-camera.direction);
+        self.target = camera.eye + camera.direction.normalize() * distance;
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.distance = distance;
+        self.goal_target = self.target;
+        self.goal_yaw = yaw;
+        self.goal_pitch = pitch;
+        self.goal_distance = distance
+    }
+
+    pub fn target(&self) -> Point3<f32> {
+        self.target
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// whether a drag is in progress, the cursor should be grabbed and hidden meanwhile
+    pub fn dragging(&self) -> bool {
+        self.dragging
+    }
+
+    /// moves the goal by the mouse input of this frame. has to be called once per frame,
+    /// unlike `update_camera` which may run several times.
+    pub fn handle_input(&mut self, input: &InputManager) {
+        let (dx, dy) = input.mouse_motion();
+        let rotating = input.is_mouse_button_pressed(MouseButton::Left);
+        let panning = input.is_mouse_button_pressed(MouseButton::MouseWheel);
+        self.dragging = rotating || panning;
+
+        if rotating {
+            self.goal_yaw += Rad(dx * self.sensitivity);
+            self.goal_pitch =
+                Rad((self.goal_pitch.0 + dy * self.sensitivity).clamp(-MAX_PITCH.0, MAX_PITCH.0));
         }
+        if panning {
+            let direction = -direction_from_yaw_pitch(self.goal_yaw, self.goal_pitch);
+            let right = direction.cross(Vector3::unit_y()).normalize();
+            let up = right.cross(direction);
+            let scale = self.pan_speed * self.goal_distance;
+            self.goal_target += (up * dy - right * dx) * scale;
+        }
+
+        let (_, wheel) = input.mouse_delta();
+        if wheel != 0.0 {
+            self.goal_distance = (self.goal_distance * ZOOM_FACTOR.powf(-wheel))
+                .clamp(self.min_distance, self.max_distance);
+        }
+    }
+
+    /// eases towards the goal and places the camera on the orbit
+    pub fn update_camera(&mut self, camera: &mut Camera, time_delta: Duration) {
+        let blend = 1.0 - (-self.damping * time_delta.as_secs_f32()).exp();
+        self.target = Point3::from_vec(self.target.to_vec().lerp(self.goal_target.to_vec(), blend));
+        self.yaw += (self.goal_yaw - self.yaw) * blend;
+        self.pitch += (self.goal_pitch - self.pitch) * blend;
+        self.distance += (self.goal_distance - self.distance) * blend;
+
+        let offset = direction_from_yaw_pitch(self.yaw, self.pitch);
+        camera.eye = self.target + offset * self.distance;
+        camera.direction = -offset;
+    }
+}
+
+/// walks on a height field. WASD moves along the ground, shift sprints and space jumps, looking
+/// around is left to `CameraController::handle_input`.
+pub struct WalkCameraController {
+    /// meters per second
+    pub speed: f32,
+    pub sprint_multiplier: f32,
+    /// distance from the ground to the eye
+    pub eye_height: f32,
+    /// meters per second squared
+    pub gravity: f32,
+    /// initial upwards velocity of a jump
+    pub jump_speed: f32,
+    /// steeper ground can't be walked up
+    pub max_slope: Rad<f32>,
+    /// how far the ground may drop away while still being followed instead of falling
+    pub step_down: f32,
+    vertical_velocity: f32,
+    grounded: bool,
+}
+
+impl WalkCameraController {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            sprint_multiplier: 2.0,
+            eye_height: 1.7,
+            gravity: 9.81,
+            jump_speed: 4.5,
+            max_slope: Rad(45.0 * std::f32::consts::PI / 180.0),
+            step_down: 0.3,
+            vertical_velocity: 0.0,
+            grounded: false,
+        }
+    }
+
+    pub fn grounded(&self) -> bool {
+        self.grounded
+    }
+
+    pub fn update_camera(
+        &mut self,
+        camera: &mut Camera,
+        input: &InputManager,
+        time_delta: Duration,
+        terrain: &HeightField,
+    ) {
+        let time_delta = time_delta.as_secs_f32();
+        let forward = Vector3::new(camera.direction.x, 0.0, camera.direction.z);
+        let forward = if forward.magnitude2() > f32::EPSILON {
+            forward.normalize()
+        } else {
+            Vector3::unit_z()
+        };
+        let right = forward.cross(Vector3::unit_y());
+
+        let mut movement = Vector3::new(0.0, 0.0, 0.0);
+        if input.is_key_pressed(KeyCode::W) {
+            movement += forward
+        }
+        if input.is_key_pressed(KeyCode::S) {
+            movement -= forward
+        }
+        if input.is_key_pressed(KeyCode::A) {
+            movement -= right
+        }
+        if input.is_key_pressed(KeyCode::D) {
+            movement += right
+        }
+        if movement.magnitude2() > f32::EPSILON {
+            let speed = if input.is_key_pressed(KeyCode::LShift) {
+                self.speed * self.sprint_multiplier
+            } else {
+                self.speed
+            };
+            let step = movement.normalize() * speed * time_delta;
+            let ground = terrain.height_at(camera.eye.x, camera.eye.z);
+            let next_ground = terrain.height_at(camera.eye.x + step.x, camera.eye.z + step.z);
+            let too_steep = next_ground - ground > step.magnitude() * self.max_slope.tan();
+            if !(self.grounded && too_steep) {
+                camera.eye += step
+            }
+        }
+
+        if self.grounded && input.is_key_pressed(KeyCode::Space) {
+            self.vertical_velocity = self.jump_speed;
+            self.grounded = false
+        }
+        self.vertical_velocity -= self.gravity * time_delta;
+        camera.eye.y += self.vertical_velocity * time_delta;
+
+        let floor = terrain.height_at(camera.eye.x, camera.eye.z) + self.eye_height;
+        let following = self.grounded && self.vertical_velocity <= 0.0;
+        if camera.eye.y <= floor || (following && camera.eye.y - floor <= self.step_down) {
+            camera.eye.y = floor;
+            self.vertical_velocity = 0.0;
+            self.grounded = true
+        } else {
+            self.grounded = false
+        }
+    }
+}
+
+/// perspective with the far plane at infinity, depth approaches 1 with distance
+fn infinite_perspective(fovy: Deg<f32>, aspect: f32, near: f32) -> Matrix4<f32> {
+    let f = 1.0 / (Rad::from(fovy) / 2.0).tan();
+    #[rustfmt::skip]
+    let matrix = Matrix4::new(
+        f / aspect, 0.0, 0.0, 0.0,
+        0.0, f, 0.0, 0.0,
+        0.0, 0.0, -1.0, -1.0,
+        0.0, 0.0, -near, 0.0,
+    );
+    matrix
+}
+
+/// yaw is measured around y from the x axis towards z, pitch up from the horizon
+pub fn yaw_pitch(direction: Vector3<f32>) -> (Rad<f32>, Rad<f32>) {
+    let direction = direction.normalize();
+    (
+        Rad(direction.z.atan2(direction.x)),
+        Rad(direction.y.clamp(-1.0, 1.0).asin()),
+    )
+}
+
+/// inverse of `yaw_pitch`, the pitch is clamped to `MAX_PITCH`
+pub fn direction_from_yaw_pitch(yaw: Rad<f32>, pitch: Rad<f32>) -> Vector3<f32> {
+    let pitch = Rad(pitch.0.clamp(-MAX_PITCH.0, MAX_PITCH.0));
+    Vector3::new(
+        yaw.cos() * pitch.cos(),
+        pitch.sin(),
+        yaw.sin() * pitch.cos(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_close_vector;
+
+    #[test]
+    fn yaw_pitch_round_trips() {
+        let direction = Vector3::new(0.3, -0.4, 0.8).normalize();
+        let (yaw, pitch) = yaw_pitch(direction);
+        assert_close_vector(direction_from_yaw_pitch(yaw, pitch), direction);
+    }
+
+    #[test]
+    fn pitch_is_clamped() {
+        let direction = direction_from_yaw_pitch(Rad(0.0), Rad(std::f32::consts::PI));
+        let (_, pitch) = yaw_pitch(direction);
+        assert!((pitch.0 - MAX_PITCH.0).abs() < 1e-4);
+        assert!(direction.y < 1.0);
+    }
+
+    #[test]
+    fn orbit_keeps_the_camera_in_place_when_focusing() {
+        let mut camera = Camera {
+            eye: Point3::new(1.0, 2.0, 3.0),
+            direction: Vector3::new(0.0, -1.0, 1.0).normalize(),
+            ..Camera::test_default()
+        };
+        let before = camera.clone();
+        let mut orbit = OrbitCameraController::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+        orbit.focus(&camera, 5.0);
+        orbit.update_camera(&mut camera, Duration::from_millis(16));
+        assert_close_vector(camera.eye.to_vec(), before.eye.to_vec());
+        assert_close_vector(camera.direction, before.direction);
+        assert!(((camera.eye - orbit.target()).magnitude() - 5.0).abs() < 1e-4);
+    }
+
+    fn press(input: &mut InputManager, key: winit::event::VirtualKeyCode) {
+        input.update(winit::event::WindowEvent::KeyboardInput {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            input: winit::event::KeyboardInput {
+                scancode: 0,
+                state: winit::event::ElementState::Pressed,
+                virtual_keycode: Some(key),
+                #[allow(deprecated)]
+                modifiers: winit::event::ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        });
+    }
+
+    #[test]
+    fn walking_falls_to_the_ground_and_jumps() {
+        let terrain = HeightField::flat(100.0, 2.0);
+        let mut camera = Camera::test_default();
+        camera.eye.y = 10.0;
+        let mut walk = WalkCameraController::new(1.0);
+        let mut input = InputManager::new();
+        let step = Duration::from_millis(10);
+        for _ in 0..200 {
+            walk.update_camera(&mut camera, &input, step, &terrain);
+        }
+        assert!(walk.grounded());
+        assert!((camera.eye.y - 3.7).abs() < 1e-5);
+
+        press(&mut input, winit::event::VirtualKeyCode::Space);
+        walk.update_camera(&mut camera, &input, step, &terrain);
+        assert!(!walk.grounded());
+        assert!(camera.eye.y > 3.7);
+    }
+
+    #[test]
+    fn walking_stops_at_steep_slopes() {
+        let terrain = HeightField::from_fn((-10.0, -10.0), 1.0, 21, 21, |x, _| x.max(0.0) * 2.0);
+        let mut camera = Camera::test_default();
+        camera.eye.x = -1.0;
+        let mut walk = WalkCameraController::new(1.0);
+        let mut input = InputManager::new();
+        walk.update_camera(&mut camera, &input, Duration::ZERO, &terrain);
+        press(&mut input, winit::event::VirtualKeyCode::W);
+        for _ in 0..200 {
+            walk.update_camera(&mut camera, &input, Duration::from_millis(10), &terrain);
+        }
+        assert!(camera.eye.x < 0.1);
+        assert!((camera.eye.y - terrain.height_at(camera.eye.x, 0.0) - 1.7).abs() < 1e-5);
+    }
+
+    fn depth(camera: &Camera, distance: f32) -> f32 {
+        let clip = camera.build_view_projection_matrix()
+            * (camera.eye + camera.direction * distance).to_homogeneous();
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn depth_conventions() {
+        let mut camera = Camera::test_default();
+        assert!(depth(&camera, camera.near).abs() < 1e-5);
+        assert!((depth(&camera, camera.far) - 1.0).abs() < 1e-5);
+
+        camera.reversed_z = true;
+        assert!((depth(&camera, camera.near) - 1.0).abs() < 1e-5);
+        assert!(depth(&camera, camera.far).abs() < 1e-5);
+
+        camera.infinite_far = true;
+        assert!((depth(&camera, camera.near) - 1.0).abs() < 1e-5);
+        assert!(depth(&camera, 1e6) > 0.0);
+        assert!(depth(&camera, 1e6) < 1e-6);
+        assert_eq!(camera.far_depth(), 0.0);
+    }
+
+    #[test]
+    fn orthographic_follows_the_aspect_ratio() {
+        let mut camera = Camera::test_default();
+        camera.projection = Projection::Orthographic { height: 10.0 };
+        camera.aspect = 2.0;
+        let corner = camera.build_view_projection_matrix()
+            * (camera.eye + camera.direction * 10.0 + Vector3::new(0.0, 5.0, 10.0))
+                .to_homogeneous();
+        assert!((corner.x / corner.w - 1.0).abs() < 1e-5);
+        assert!((corner.y / corner.w - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn top_down_looks_down_with_the_heading_up() {
+        let mut camera = Camera::test_default();
+        camera.projection = Projection::TopDown { height: 10.0 };
+        let ahead = camera.build_view_projection_matrix()
+            * (camera.eye + Vector3::new(5.0, -3.0, 0.0)).to_homogeneous();
+        assert!(ahead.x.abs() < 1e-5);
+        assert!((ahead.y / ahead.w - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn strafing_follows_the_view_direction() {
+        let mut camera = Camera::test_default();
+        let mut input = InputManager::new();
+        press(&mut input, winit::event::VirtualKeyCode::D);
+        CameraController::new(1.0).update_camera(&mut camera, &input, Duration::from_secs(2));
+        assert_close_vector(camera.eye.to_vec(), Vector3::new(0.0, 0.0, 2.0));
     }
 }
@@ -0,0 +1,440 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, Context};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::{camera::Camera, timer::Timer};
+
+/// where the camera was at `time` into a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub time: Duration,
+    pub eye: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub fovy: f32,
+}
+
+impl Keyframe {
+    pub fn from_camera(camera: &Camera, time: Duration) -> Self {
+        Self {
+            time,
+            eye: camera.eye,
+            direction: camera.direction,
+            fovy: camera.fovy,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.eye = self.eye;
+        camera.direction = self.direction;
+        camera.fovy = self.fovy
+    }
+}
+
+/// how a path moves in between keyframes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathInterpolation {
+    Linear,
+    /// smooth curve through every keyframe
+    CatmullRom,
+    /// cubic bezier segments with their handles along the neighbouring keyframes. a tension of
+    /// 0 matches catmull-rom, 1 gives straight lines.
+    Bezier {
+        tension: f32,
+    },
+}
+
+/// keyframes sorted by time. saved as plain text with one keyframe per line:
+/// `seconds eye.x eye.y eye.z direction.x direction.y direction.z fovy`
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    pub interpolation: PathInterpolation,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self {
+            keyframes: vec![],
+            interpolation: PathInterpolation::CatmullRom,
+        }
+    }
+
+    /// keeps the keyframes sorted, a keyframe at the same time as an existing one replaces it
+    pub fn push(&mut self, keyframe: Keyframe) {
+        match self
+            .keyframes
+            .binary_search_by(|other| other.time.cmp(&keyframe.time))
+        {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// time of the last keyframe
+    pub fn duration(&self) -> Duration {
+        self.keyframes
+            .last()
+            .map(|keyframe| keyframe.time)
+            .unwrap_or_default()
+    }
+
+    /// the camera at `time`, clamped to the ends of the path
+    pub fn sample(&self, time: Duration) -> Option<Keyframe> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        if time <= first.time {
+            return Some(Keyframe { time, ..*first });
+        }
+        if time >= last.time {
+            return Some(Keyframe { time, ..*last });
+        }
+
+        let next = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        let (k1, k2) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let k0 = &self.keyframes[next.saturating_sub(2)];
+        let k3 = &self.keyframes[(next + 1).min(self.keyframes.len() - 1)];
+        let t = (time - k1.time).as_secs_f32() / (k2.time - k1.time).as_secs_f32();
+
+        let interpolation = self.interpolation;
+        let curve = |p0: Vector3<f32>, p1: Vector3<f32>, p2: Vector3<f32>, p3: Vector3<f32>| {
+            interpolate(interpolation, [p0, p1, p2, p3], t)
+        };
+        let direction = curve(k0.direction, k1.direction, k2.direction, k3.direction);
+        let fovy = curve(
+            Vector3::new(k0.fovy, 0.0, 0.0),
+            Vector3::new(k1.fovy, 0.0, 0.0),
+            Vector3::new(k2.fovy, 0.0, 0.0),
+            Vector3::new(k3.fovy, 0.0, 0.0),
+        )
+        .x;
+        Some(Keyframe {
+            time,
+            eye: Point3::from_vec(curve(
+                k0.eye.to_vec(),
+                k1.eye.to_vec(),
+                k2.eye.to_vec(),
+                k3.eye.to_vec(),
+            )),
+            direction: if direction.magnitude2() > f32::EPSILON {
+                direction.normalize()
+            } else {
+                k2.direction
+            },
+            fovy,
+        })
+    }
+
+    /// samples the path at a fixed rate, e.g. one keyframe per video frame
+    pub fn resample(&self, rate: f64) -> anyhow::Result<CameraPath> {
+        // a zero step, from a rate too high to be represented, would never reach the end
+        let step = Duration::try_from_secs_f64(1.0 / rate)
+            .ok()
+            .filter(|step| !step.is_zero())
+            .ok_or_else(|| anyhow!("invalid resampling rate {}", rate))?;
+        let mut path = CameraPath {
+            keyframes: vec![],
+            interpolation: PathInterpolation::Linear,
+        };
+        let mut time = Duration::ZERO;
+        while time < self.duration() {
+            path.keyframes.extend(self.sample(time));
+            time += step
+        }
+        path.keyframes.extend(self.sample(self.duration()));
+        Ok(path)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text =
+            String::from("# seconds eye.x eye.y eye.z direction.x direction.y direction.z fovy\n");
+        for keyframe in &self.keyframes {
+            text.push_str(&format!(
+                "{} {} {} {} {} {} {} {}\n",
+                keyframe.time.as_secs_f64(),
+                keyframe.eye.x,
+                keyframe.eye.y,
+                keyframe.eye.z,
+                keyframe.direction.x,
+                keyframe.direction.y,
+                keyframe.direction.z,
+                keyframe.fovy,
+            ))
+        }
+        text
+    }
+
+    /// empty lines and lines starting with # are skipped
+    pub fn from_text(text: &str) -> anyhow::Result<Self> {
+        let mut path = Self::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("invalid number in line {}", number + 1))?;
+            // parsing accepts nan and inf, which would poison every sample of the path
+            let [time, x, y, z, dx, dy, dz, fovy] = values[..] else {
+                return Err(anyhow!(
+                    "expected 8 values in line {}, found {}",
+                    number + 1,
+                    values.len()
+                ));
+            };
+            if values.iter().any(|&value| !(value as f32).is_finite()) {
+                return Err(anyhow!("non-finite number in line {}", number + 1));
+            }
+            if time < 0.0 {
+                return Err(anyhow!("negative time in line {}", number + 1));
+            }
+            path.push(Keyframe {
+                time: Duration::try_from_secs_f64(time)
+                    .with_context(|| format!("invalid time in line {}", number + 1))?,
+                eye: Point3::new(x as f32, y as f32, z as f32),
+                direction: Vector3::new(dx as f32, dy as f32, dz as f32),
+                fovy: fovy as f32,
+            })
+        }
+        Ok(path)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::from_text(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// blends between `points[1]` and `points[2]`, the outer points shape the tangents
+fn interpolate(
+    interpolation: PathInterpolation,
+    points: [Vector3<f32>; 4],
+    t: f32,
+) -> Vector3<f32> {
+    let [p0, p1, p2, p3] = points;
+    let tension = match interpolation {
+        PathInterpolation::Linear => return p1 + (p2 - p1) * t,
+        PathInterpolation::CatmullRom => 0.0,
+        PathInterpolation::Bezier { tension } => tension.clamp(0.0, 1.0),
+    };
+    let scale = (1.0 - tension) / 6.0;
+    let c1 = p1 + (p2 - p0) * scale;
+    let c2 = p2 - (p3 - p1) * scale;
+    let inverse = 1.0 - t;
+    p1 * (inverse * inverse * inverse)
+        + c1 * (3.0 * inverse * inverse * t)
+        + c2 * (3.0 * inverse * t * t)
+        + p2 * (t * t * t)
+}
+
+/// appends a keyframe of the camera every `interval`
+pub struct CameraPathRecorder {
+    path: CameraPath,
+    time: Duration,
+    interval: Timer,
+}
+
+impl CameraPathRecorder {
+    pub fn new(interval: Duration) -> Self {
+        let mut timer = Timer::new(interval, true);
+        timer.start();
+        Self {
+            path: CameraPath::new(),
+            time: Duration::ZERO,
+            interval: timer,
+        }
+    }
+
+    /// the first update records the starting keyframe
+    pub fn update(&mut self, camera: &Camera, time_delta: Duration) {
+        if self.path.is_empty() {
+            self.path
+                .push(Keyframe::from_camera(camera, Duration::ZERO))
+        }
+        self.time += time_delta;
+        if self.interval.update(time_delta) > 0 {
+            self.path.push(Keyframe::from_camera(camera, self.time))
+        }
+    }
+
+    /// ends with a keyframe at the current camera
+    pub fn finish(mut self, camera: &Camera) -> CameraPath {
+        self.path.push(Keyframe::from_camera(camera, self.time));
+        self.path
+    }
+}
+
+/// moves a camera along a path
+pub struct CameraPathPlayer {
+    path: CameraPath,
+    time: Duration,
+    pub looping: bool,
+}
+
+impl CameraPathPlayer {
+    pub fn new(path: CameraPath, looping: bool) -> Self {
+        Self {
+            path,
+            time: Duration::ZERO,
+            looping,
+        }
+    }
+
+    pub fn path(&self) -> &CameraPath {
+        &self.path
+    }
+
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    pub fn finished(&self) -> bool {
+        !self.looping && self.time >= self.path.duration()
+    }
+
+    /// advances by `time_delta` and places the camera on the path
+    pub fn update(&mut self, camera: &mut Camera, time_delta: Duration) {
+        self.time += time_delta;
+        let duration = self.path.duration();
+        if self.looping && !duration.is_zero() {
+            while self.time >= duration {
+                self.time -= duration
+            }
+        }
+        if let Some(keyframe) = self.path.sample(self.time) {
+            keyframe.apply(camera)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(seconds: u64, x: f32) -> Keyframe {
+        Keyframe {
+            time: Duration::from_secs(seconds),
+            eye: Point3::new(x, 0.0, 0.0),
+            direction: Vector3::unit_z(),
+            fovy: 45.0 + x,
+        }
+    }
+
+    fn path() -> CameraPath {
+        let mut path = CameraPath::new();
+        path.push(keyframe(2, 4.0));
+        path.push(keyframe(0, 0.0));
+        path.push(keyframe(1, 1.0));
+        path.push(keyframe(3, 9.0));
+        path
+    }
+
+    #[test]
+    fn curves_pass_through_keyframes() {
+        let mut path = path();
+        assert_eq!(path.duration(), Duration::from_secs(3));
+        for interpolation in [
+            PathInterpolation::Linear,
+            PathInterpolation::CatmullRom,
+            PathInterpolation::Bezier { tension: 0.5 },
+        ] {
+            path.interpolation = interpolation;
+            for keyframe in path.keyframes().to_vec() {
+                let sample = path.sample(keyframe.time).unwrap();
+                assert!((sample.eye - keyframe.eye).magnitude() < 1e-5);
+                assert!((sample.fovy - keyframe.fovy).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn full_tension_is_linear() {
+        let mut path = path();
+        path.interpolation = PathInterpolation::Bezier { tension: 1.0 };
+        let bezier = path.sample(Duration::from_millis(1500)).unwrap();
+        path.interpolation = PathInterpolation::CatmullRom;
+        let catmull_rom = path.sample(Duration::from_millis(1500)).unwrap();
+        assert!((bezier.eye.x - 2.5).abs() < 1e-5);
+        assert!((catmull_rom.eye.x - 2.5).abs() > 1e-3);
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let path = path();
+        let loaded = CameraPath::from_text(&path.to_text()).unwrap();
+        assert_eq!(loaded.keyframes(), path.keyframes());
+        assert!(CameraPath::from_text("1 2 3").is_err());
+    }
+
+    #[test]
+    fn text_keeps_fractional_and_long_times() {
+        let mut path = CameraPath::new();
+        for time in [
+            Duration::from_nanos(16_666_667),
+            Duration::from_millis(1_234_567),
+            Duration::new(40 * 24 * 60 * 60, 123_456_789),
+        ] {
+            path.push(Keyframe {
+                time,
+                ..keyframe(0, 1.0)
+            })
+        }
+        let loaded = CameraPath::from_text(&path.to_text()).unwrap();
+        assert_eq!(loaded.keyframes(), path.keyframes());
+    }
+
+    #[test]
+    fn text_rejects_non_finite_numbers() {
+        assert!(CameraPath::from_text("0 0 0 0 0 0 1 45").is_ok());
+        assert!(CameraPath::from_text("nan 0 0 0 0 0 1 45").is_err());
+        assert!(CameraPath::from_text("inf 0 0 0 0 0 1 45").is_err());
+        assert!(CameraPath::from_text("0 0 NaN 0 0 0 1 45").is_err());
+        assert!(CameraPath::from_text("0 0 0 0 0 0 1 -inf").is_err());
+        assert!(CameraPath::from_text("0 1e39 0 0 0 0 1 45").is_err());
+        assert!(CameraPath::from_text("1e30 0 0 0 0 0 1 45").is_err());
+    }
+
+    #[test]
+    fn resampling_needs_a_positive_rate() {
+        let resampled = path().resample(2.0).unwrap();
+        assert_eq!(resampled.keyframes().len(), 7);
+        assert_eq!(resampled.keyframes()[3].time, Duration::from_millis(1500));
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e12] {
+            assert!(path().resample(rate).is_err(), "rate {}", rate);
+        }
+    }
+
+    #[test]
+    fn player_loops() {
+        let mut camera = Camera::test_default();
+        let mut player = CameraPathPlayer::new(path(), true);
+        player.update(&mut camera, Duration::from_millis(4000));
+        assert_eq!(player.time(), Duration::from_secs(1));
+        assert_eq!(camera.eye, Point3::new(1.0, 0.0, 0.0));
+        assert_eq!(camera.direction, Vector3::unit_z());
+        assert!(!player.finished());
+    }
+}
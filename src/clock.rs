@@ -0,0 +1,84 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// source of the current time. `Timing` derives every time delta from one, so swapping in a
+/// `ManualClock` makes it deterministic.
+pub trait Clock {
+    /// time since the clock was created, never decreasing
+    fn now(&self) -> Duration;
+}
+
+/// wall clock time
+#[derive(Debug, Clone)]
+pub struct RealClock {
+    start: Instant,
+}
+
+impl RealClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// only moves when advanced explicitly, for tests and replays. clones share the same time, so
+/// one can be handed to `Timing` and the other kept to drive it.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, time_delta: Duration) {
+        *self.now.lock().unwrap() += time_delta
+    }
+
+    /// ignored if it would move the clock backwards
+    pub fn set(&self, now: Duration) {
+        let mut current = self.now.lock().unwrap();
+        *current = (*current).max(now)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_forward() {
+        let clock = ManualClock::new();
+        let shared = clock.clone();
+        assert_eq!(clock.now(), Duration::ZERO);
+        shared.advance(Duration::from_millis(10));
+        assert_eq!(clock.now(), Duration::from_millis(10));
+        shared.set(Duration::from_millis(5));
+        assert_eq!(clock.now(), Duration::from_millis(10));
+        shared.set(Duration::from_secs(1));
+        assert_eq!(clock.now(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,380 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, Vector3, Vector4};
+
+use crate::model::Mesh;
+
+/// points with a positive signed distance are in front of the plane
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    /// normalizes `a * x + b * y + c * z + d = 0`. a plane at infinity has no normal, it is kept
+    /// as is so every point stays in front of it.
+    fn from_coefficients(coefficients: Vector4<f32>) -> Self {
+        let normal = coefficients.truncate();
+        let length = normal.magnitude();
+        if length <= f32::EPSILON {
+            return Self {
+                normal: Vector3::new(0.0, 0.0, 0.0),
+                distance: coefficients.w.abs(),
+            };
+        }
+        Self {
+            normal: normal / length,
+            distance: coefficients.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: Point3<f32>) -> f32 {
+        self.normal.dot(point.to_vec()) + self.distance
+    }
+}
+
+/// how much of a volume lies within a frustum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intersection {
+    Outside,
+    Intersecting,
+    Inside,
+}
+
+/// the six planes bounding what a camera sees, facing inwards
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// left, right, bottom, top, near and far. near and far swap with reversed z, which
+    /// doesn't matter for culling.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// extracts the planes from a view projection matrix with wgpu's [0, 1] depth range, such
+    /// as `Camera::build_view_projection_matrix`
+    pub fn from_matrix(matrix: Matrix4<f32>) -> Self {
+        let row = |index| matrix.row(index);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z].map(Plane::from_coefficients),
+        }
+    }
+
+    pub fn contains_point(&self, point: Point3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    pub fn test_sphere(&self, sphere: &BoundingSphere) -> Intersection {
+        let mut intersection = Intersection::Inside;
+        for plane in &self.planes {
+            let distance = plane.signed_distance(sphere.center);
+            if distance < -sphere.radius {
+                return Intersection::Outside;
+            }
+            if distance < sphere.radius {
+                intersection = Intersection::Intersecting
+            }
+        }
+        intersection
+    }
+
+    /// conservative, a box near a frustum corner may count as intersecting although it is
+    /// outside
+    pub fn test_aabb(&self, aabb: &Aabb) -> Intersection {
+        let mut intersection = Intersection::Inside;
+        for plane in &self.planes {
+            let pick = |positive: bool, axis: usize| {
+                if positive {
+                    aabb.max[axis]
+                } else {
+                    aabb.min[axis]
+                }
+            };
+            let normal = plane.normal;
+            let farthest = Point3::new(
+                pick(normal.x >= 0.0, 0),
+                pick(normal.y >= 0.0, 1),
+                pick(normal.z >= 0.0, 2),
+            );
+            if plane.signed_distance(farthest) < 0.0 {
+                return Intersection::Outside;
+            }
+            let nearest = Point3::new(
+                pick(normal.x < 0.0, 0),
+                pick(normal.y < 0.0, 1),
+                pick(normal.z < 0.0, 2),
+            );
+            if plane.signed_distance(nearest) < 0.0 {
+                intersection = Intersection::Intersecting
+            }
+        }
+        intersection
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.test_sphere(sphere) != Intersection::Outside
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.test_aabb(aabb) != Intersection::Outside
+    }
+}
+
+/// axis aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// `None` for no points
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::new(first, first), |aabb, point| Self {
+            min: Point3::new(
+                aabb.min.x.min(point.x),
+                aabb.min.y.min(point.y),
+                aabb.min.z.min(point.z),
+            ),
+            max: Point3::new(
+                aabb.max.x.max(point.x),
+                aabb.max.y.max(point.y),
+                aabb.max.z.max(point.z),
+            ),
+        }))
+    }
+
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        Self::from_points(
+            mesh.vertices
+                .iter()
+                .map(|vertex| Point3::from(vertex.position())),
+        )
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    pub fn extents(&self) -> Vector3<f32> {
+        (self.max - self.min) / 2.0
+    }
+
+    pub fn contains(&self, point: Point3<f32>) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Point3<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// centered on the bounding box of the vertices, not the tightest sphere but close enough
+    /// for culling
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        let center = Aabb::from_mesh(mesh)?.center();
+        let radius = mesh
+            .vertices
+            .iter()
+            .map(|vertex| (Point3::from(vertex.position()) - center).magnitude())
+            .fold(0.0, f32::max);
+        Some(Self { center, radius })
+    }
+
+    pub fn contains(&self, point: Point3<f32>) -> bool {
+        (point - self.center).magnitude2() <= self.radius * self.radius
+    }
+
+    pub fn intersects(&self, other: &BoundingSphere) -> bool {
+        let radius = self.radius + other.radius;
+        (other.center - self.center).magnitude2() <= radius * radius
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let closest = Point3::new(
+            self.center.x.clamp(aabb.min.x, aabb.max.x),
+            self.center.y.clamp(aabb.min.y, aabb.max.y),
+            self.center.z.clamp(aabb.min.z, aabb.max.z),
+        );
+        self.contains(closest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    /// at the origin looking down -z, near at 1 and far at 10
+    fn camera() -> Camera {
+        Camera {
+            direction: -Vector3::unit_z(),
+            fovy: 90.0,
+            near: 1.0,
+            far: 10.0,
+            ..Camera::test_default()
+        }
+    }
+
+    fn sphere_at(z: f32, radius: f32) -> BoundingSphere {
+        BoundingSphere::new(Point3::new(0.0, 0.0, z), radius)
+    }
+
+    fn frustums() -> Vec<Frustum> {
+        let mut reversed = camera();
+        reversed.reversed_z = true;
+        [camera(), reversed]
+            .iter()
+            .map(|camera| Frustum::from_matrix(camera.build_view_projection_matrix()))
+            .collect()
+    }
+
+    #[test]
+    fn near_plane_edges() {
+        for frustum in frustums() {
+            assert_eq!(
+                frustum.test_sphere(&sphere_at(-0.5, 0.1)),
+                Intersection::Outside
+            );
+            assert_eq!(
+                frustum.test_sphere(&sphere_at(-1.0, 0.1)),
+                Intersection::Intersecting
+            );
+            assert_eq!(
+                frustum.test_sphere(&sphere_at(-1.5, 0.1)),
+                Intersection::Inside
+            );
+            assert!(frustum.contains_point(Point3::new(0.0, 0.0, -1.0)));
+            assert!(!frustum.contains_point(Point3::new(0.0, 0.0, -0.999)));
+        }
+    }
+
+    #[test]
+    fn far_plane_edges() {
+        for frustum in frustums() {
+            assert_eq!(
+                frustum.test_sphere(&sphere_at(-9.5, 0.1)),
+                Intersection::Inside
+            );
+            assert_eq!(
+                frustum.test_sphere(&sphere_at(-10.0, 0.1)),
+                Intersection::Intersecting
+            );
+            assert_eq!(
+                frustum.test_sphere(&sphere_at(-10.5, 0.1)),
+                Intersection::Outside
+            );
+            let beyond = Aabb::new(Point3::new(-1.0, -1.0, -12.0), Point3::new(1.0, 1.0, -10.1));
+            assert_eq!(frustum.test_aabb(&beyond), Intersection::Outside);
+            let straddling = Aabb::new(Point3::new(-1.0, -1.0, -12.0), Point3::new(1.0, 1.0, -9.0));
+            assert_eq!(frustum.test_aabb(&straddling), Intersection::Intersecting);
+        }
+    }
+
+    #[test]
+    fn infinite_far_plane_never_culls() {
+        let mut camera = camera();
+        camera.infinite_far = true;
+        for reversed_z in [false, true] {
+            camera.reversed_z = reversed_z;
+            let frustum = Frustum::from_matrix(camera.build_view_projection_matrix());
+            assert_eq!(
+                frustum.test_sphere(&sphere_at(-1e5, 1.0)),
+                Intersection::Inside
+            );
+            assert_eq!(
+                frustum.test_sphere(&sphere_at(-0.5, 0.1)),
+                Intersection::Outside
+            );
+        }
+    }
+
+    #[test]
+    fn side_planes() {
+        let frustum = &frustums()[0];
+        // with a 90 degree fov the sides are at 45 degrees
+        let left = Aabb::new(Point3::new(-7.0, -1.0, -5.0), Point3::new(-5.5, 1.0, -4.0));
+        assert_eq!(frustum.test_aabb(&left), Intersection::Outside);
+        let center = Aabb::new(Point3::new(-1.0, -1.0, -5.0), Point3::new(1.0, 1.0, -4.0));
+        assert_eq!(frustum.test_aabb(&center), Intersection::Inside);
+        assert!(!frustum.intersects_sphere(&BoundingSphere::new(Point3::new(0.0, 8.0, -5.0), 1.0)));
+    }
+
+    #[test]
+    fn aabb_contains_and_intersects() {
+        let aabb = Aabb::new(Point3::new(-1.0, 0.0, -2.0), Point3::new(1.0, 2.0, 2.0));
+        assert_eq!(aabb.center(), Point3::new(0.0, 1.0, 0.0));
+        assert_eq!(aabb.extents(), Vector3::new(1.0, 1.0, 2.0));
+        assert!(aabb.contains(Point3::new(1.0, 0.0, 0.0)));
+        assert!(!aabb.contains(Point3::new(1.1, 0.0, 0.0)));
+
+        let touching = Aabb::new(Point3::new(1.0, 2.0, 2.0), Point3::new(3.0, 3.0, 3.0));
+        assert!(aabb.intersects(&touching));
+        assert!(touching.intersects(&aabb));
+        let apart = Aabb::new(Point3::new(-1.0, 2.5, -2.0), Point3::new(1.0, 3.0, 2.0));
+        assert!(!aabb.intersects(&apart));
+    }
+
+    #[test]
+    fn sphere_contains_and_intersects() {
+        let sphere = BoundingSphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+        assert!(sphere.contains(Point3::new(0.0, 1.0, 0.0)));
+        assert!(!sphere.contains(Point3::new(0.8, 0.8, 0.0)));
+
+        assert!(sphere.intersects(&BoundingSphere::new(Point3::new(3.0, 0.0, 0.0), 2.0)));
+        assert!(!sphere.intersects(&BoundingSphere::new(Point3::new(3.0, 0.0, 0.0), 1.9)));
+
+        // the planes of the box's faces come within the radius, its nearest corner doesn't
+        let corner = Aabb::new(Point3::new(0.8, 0.8, -1.0), Point3::new(2.0, 2.0, 1.0));
+        assert!(!sphere.intersects_aabb(&corner));
+        let face = Aabb::new(Point3::new(0.9, -1.0, -1.0), Point3::new(2.0, 1.0, 1.0));
+        assert!(sphere.intersects_aabb(&face));
+        let around = Aabb::new(Point3::new(-5.0, -5.0, -5.0), Point3::new(5.0, 5.0, 5.0));
+        assert!(sphere.intersects_aabb(&around));
+    }
+
+    #[test]
+    fn frustum_intersection_shortcuts() {
+        for frustum in frustums() {
+            assert!(frustum.intersects_sphere(&sphere_at(-10.0, 0.1)));
+            assert!(!frustum.intersects_sphere(&sphere_at(-10.5, 0.1)));
+            let straddling = Aabb::new(Point3::new(-1.0, -1.0, -12.0), Point3::new(1.0, 1.0, -9.0));
+            assert!(frustum.intersects_aabb(&straddling));
+            let behind = Aabb::new(Point3::new(-1.0, -1.0, 1.0), Point3::new(1.0, 1.0, 2.0));
+            assert!(!frustum.intersects_aabb(&behind));
+            assert!(frustum.contains_point(Point3::new(0.0, 0.0, -5.0)));
+            assert!(!frustum.contains_point(Point3::new(6.0, 0.0, -5.0)));
+        }
+    }
+
+    #[test]
+    fn bounds_from_mesh() {
+        let mesh = Mesh::create_rectangle();
+        let aabb = Aabb::from_mesh(&mesh).unwrap();
+        assert_eq!(aabb.min, Point3::new(-1.0, -1.0, 0.0));
+        assert_eq!(aabb.max, Point3::new(1.0, 1.0, 0.0));
+        let sphere = BoundingSphere::from_mesh(&mesh).unwrap();
+        assert_eq!(sphere.center, Point3::new(0.0, 0.0, 0.0));
+        assert!((sphere.radius - 2f32.sqrt()).abs() < 1e-6);
+        assert!(sphere.intersects_aabb(&aabb));
+        assert!(Aabb::from_mesh(&Mesh::new(&[], &[])).is_none());
+    }
+}
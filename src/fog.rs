@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use cgmath::Vector3;
+
+use crate::tween::{Animation, Easing, Tween};
+
+/// exponential distance fog combined with height fog, applied by every shader drawing into the
+/// main pass
+pub struct Fog {
+    pub enabled: bool,
+    pub color: Vector3<f32>,
+    /// takes the fog color from the sky in view direction instead of `color`
+    pub use_sky_color: bool,
+    pub density: f32,
+    /// distance from the camera at which the fog starts
+    pub start: f32,
+    /// height below which the height fog has its full density
+    pub height: f32,
+    pub height_density: f32,
+    /// how quickly the height fog thins out above `height`
+    pub height_falloff: f32,
+    /// strength going from 0 to 1 or back while fading in or out
+    fade: Option<Tween<f32>>,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            color: Vector3::new(0.75, 0.78, 0.85),
+            use_sky_color: true,
+            density: 0.01,
+            start: 5.0,
+            height: 0.0,
+            height_density: 0.04,
+            height_falloff: 0.3,
+            fade: None,
+        }
+    }
+}
+
+impl Fog {
+    /// fades the fog in or out over `duration` instead of switching it at once, starting from the
+    /// current strength when a fade is already running
+    pub fn fade(&mut self, enabled: bool, duration: Duration) {
+        let to = if enabled { 1.0 } else { 0.0 };
+        self.fade = Some(Tween::new(self.strength(), to, duration).easing(Easing::QuadInOut));
+        self.enabled = enabled;
+    }
+
+    pub fn update(&mut self, time_delta: Duration) {
+        if let Some(fade) = &mut self.fade {
+            fade.update(time_delta);
+            if fade.finished() {
+                self.fade = None
+            }
+        }
+    }
+
+    /// scales the fog, 0 while disabled and 1 while enabled, in between during a fade
+    pub fn strength(&self) -> f32 {
+        match &self.fade {
+            Some(fade) => fade.value(),
+            None if self.enabled => 1.0,
+            None => 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FogUniform {
+    // w is 1.0 if the color is taken from the sky
+    color: [f32; 4],
+    density: f32,
+    start: f32,
+    height: f32,
+    height_density: f32,
+    height_falloff: f32,
+    far: f32,
+    // multiplies the fog factor, 0.0 disables the fog
+    strength: f32,
+    procedural_sky: f32,
+}
+
+impl FogUniform {
+    pub fn new() -> Self {
+        Self {
+            color: [0.0; 4],
+            density: 0.0,
+            start: 0.0,
+            height: 0.0,
+            height_density: 0.0,
+            height_falloff: 0.0,
+            far: 100.0,
+            strength: 0.0,
+            procedural_sky: 0.0,
+        }
+    }
+
+    /// `far` is the camera's far plane, at which everything is fully fogged. `procedural_sky`
+    /// selects where shaders look up the sky color.
+    pub fn update(&mut self, fog: &Fog, far: f32, procedural_sky: bool) {
+        self.color = fog
+            .color
+            .extend(if fog.use_sky_color { 1.0 } else { 0.0 })
+            .into();
+        self.density = fog.density;
+        self.start = fog.start;
+        self.height = fog.height;
+        self.height_density = fog.height_density;
+        self.height_falloff = fog.height_falloff;
+        self.far = far;
+        self.strength = fog.strength();
+        self.procedural_sky = if procedural_sky { 1.0 } else { 0.0 };
+    }
+}
+
+impl Default for FogUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{assert_close, ms};
+
+    #[test]
+    fn fading_out_disables_the_fog_at_the_end() {
+        let mut fog = Fog::default();
+        fog.fade(false, ms(100));
+        assert!(!fog.enabled);
+        assert_eq!(fog.strength(), 1.0);
+        fog.update(ms(50));
+        assert_close(fog.strength(), 0.5);
+        fog.update(ms(50));
+        assert_eq!(fog.strength(), 0.0);
+
+        fog.fade(true, ms(100));
+        assert_eq!(fog.strength(), 0.0);
+        fog.update(ms(100));
+        assert_eq!(fog.strength(), 1.0);
+    }
+}
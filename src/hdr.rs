@@ -0,0 +1,385 @@
+use wgpu::util::DeviceExt;
+
+use crate::render_graph::{RenderGraph, ResourceHandle};
+
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemapper {
+    Reinhard,
+    Aces,
+    Agx,
+}
+
+impl Tonemapper {
+    pub fn next(&self) -> Self {
+        match self {
+            Tonemapper::Reinhard => Tonemapper::Aces,
+            Tonemapper::Aces => Tonemapper::Agx,
+            Tonemapper::Agx => Tonemapper::Reinhard,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    tonemapper: u32,
+    auto_exposure: u32,
+    _padding: u32,
+    exposure: f32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation_speed: f32,
+    time_delta: f32,
+}
+
+/// compute pipelines building a luminance histogram of the hdr target and averaging it into the
+/// adapted scene luminance
+struct AutoExposure {
+    histogram_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    build_histogram_pipeline: wgpu::ComputePipeline,
+    average_histogram_pipeline: wgpu::ComputePipeline,
+}
+
+/// tonemaps the floating point scene color into a linear low dynamic range target with optional
+/// auto exposure
+pub struct Hdr {
+    pub tonemapper: Tonemapper,
+    /// applied before tonemapping. with auto exposure it acts as exposure compensation.
+    pub exposure: f32,
+    /// log2 luminance range covered by the auto exposure histogram
+    pub min_log_luminance: f32,
+    pub log_luminance_range: f32,
+    /// how fast auto exposure adapts to changes in brightness
+    pub adaptation_speed: f32,
+    auto_exposure_enabled: bool,
+    uniform: TonemapUniform,
+    uniform_buffer: wgpu::Buffer,
+    luminance_buffer: wgpu::Buffer,
+    tonemap_bind_group_layout: wgpu::BindGroupLayout,
+    tonemap_pipeline: wgpu::RenderPipeline,
+    /// none if the adapter doesn't support compute shaders
+    auto_exposure: Option<AutoExposure>,
+}
+
+impl Hdr {
+    pub fn new(
+        device: &wgpu::Device,
+        output_format: wgpu::TextureFormat,
+        compute_supported: bool,
+    ) -> Self {
+        let uniform = TonemapUniform {
+            tonemapper: Tonemapper::Aces as u32,
+            auto_exposure: 0,
+            _padding: 0,
+            exposure: 1.0,
+            min_log_luminance: -8.0,
+            log_luminance_range: 12.0,
+            adaptation_speed: 1.5,
+            time_delta: 0.0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tonemap_buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let luminance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("luminance_buffer"),
+            contents: bytemuck::cast_slice(&[1.0f32]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let tonemap_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("tonemap_bind_group_layout"),
+                entries: &[
+                    hdr_texture_entry(0, wgpu::ShaderStages::FRAGMENT),
+                    uniform_entry(1, wgpu::ShaderStages::FRAGMENT),
+                    storage_entry(2, wgpu::ShaderStages::FRAGMENT, true),
+                ],
+            });
+        let tonemap_pipeline =
+            Self::create_tonemap_pipeline(device, &tonemap_bind_group_layout, output_format);
+
+        let auto_exposure = compute_supported.then(|| Self::create_auto_exposure(device));
+
+        Self {
+            tonemapper: Tonemapper::Aces,
+            exposure: uniform.exposure,
+            min_log_luminance: uniform.min_log_luminance,
+            log_luminance_range: uniform.log_luminance_range,
+            adaptation_speed: uniform.adaptation_speed,
+            auto_exposure_enabled: false,
+            uniform,
+            uniform_buffer,
+            luminance_buffer,
+            tonemap_bind_group_layout,
+            tonemap_pipeline,
+            auto_exposure,
+        }
+    }
+
+    pub fn auto_exposure(&self) -> bool {
+        self.auto_exposure_enabled
+    }
+
+    pub fn set_auto_exposure(&mut self, enabled: bool) {
+        if enabled && self.auto_exposure.is_none() {
+            tracing::warn!("auto exposure requires compute shader support");
+            return;
+        }
+        self.auto_exposure_enabled = enabled
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, time_delta: std::time::Duration) {
+        self.uniform.tonemapper = self.tonemapper as u32;
+        self.uniform.auto_exposure = self.auto_exposure_enabled as u32;
+        self.uniform.exposure = self.exposure;
+        self.uniform.min_log_luminance = self.min_log_luminance;
+        self.uniform.log_luminance_range = self.log_luminance_range;
+        self.uniform.adaptation_speed = self.adaptation_speed;
+        self.uniform.time_delta = time_delta.as_secs_f32();
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    /// adds auto exposure, if enabled, and tonemapping of `hdr` into `output`, which has to be of
+    /// the output format. returns the tonemapped output.
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        hdr: ResourceHandle,
+        output: ResourceHandle,
+    ) -> ResourceHandle {
+        let mut luminance = graph.import_buffer("luminance", &self.luminance_buffer);
+
+        if let Some(auto_exposure) = self
+            .auto_exposure
+            .as_ref()
+            .filter(|_| self.auto_exposure_enabled)
+        {
+            let mut pass = graph.add_pass("auto_exposure_pass");
+            pass.read(hdr);
+            luminance = pass.write(luminance);
+            pass.execute(move |context| {
+                let texture = context.resources.texture(hdr);
+                let bind_group = context
+                    .device
+                    .create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("auto_exposure_bind_group"),
+                        layout: &auto_exposure.bind_group_layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: wgpu::BindingResource::TextureView(&texture.view),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 1,
+                                resource: self.uniform_buffer.as_entire_binding(),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 2,
+                                resource: auto_exposure.histogram_buffer.as_entire_binding(),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 3,
+                                resource: self.luminance_buffer.as_entire_binding(),
+                            },
+                        ],
+                    });
+                let size = texture.texture.size();
+                let mut compute_pass =
+                    context
+                        .encoder
+                        .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some("auto_exposure_pass"),
+                        });
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.set_pipeline(&auto_exposure.build_histogram_pipeline);
+                compute_pass.dispatch_workgroups(
+                    size.width.div_ceil(16),
+                    size.height.div_ceil(16),
+                    1,
+                );
+                compute_pass.set_pipeline(&auto_exposure.average_histogram_pipeline);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+            });
+        }
+
+        let mut pass = graph.add_pass("tonemap_pass");
+        pass.read(hdr);
+        pass.read(luminance);
+        let output = pass.write(output);
+        pass.execute(move |context| {
+            let bind_group = context
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("tonemap_bind_group"),
+                    layout: &self.tonemap_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(
+                                context.resources.view(hdr),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: self.uniform_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: self.luminance_buffer.as_entire_binding(),
+                        },
+                    ],
+                });
+            let mut render_pass = context
+                .encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("tonemap_pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: context.resources.view(output),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+            render_pass.set_pipeline(&self.tonemap_pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        });
+        output
+    }
+
+    fn create_tonemap_pipeline(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("tonemap_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(concat!(
+                include_str!("./shaders/common.wgsl"),
+                include_str!("./shaders/tonemap.wgsl")
+            ))),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("tonemap_pipeline_layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("tonemap_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(output_format.into())],
+            }),
+            multiview: None,
+        })
+    }
+
+    fn create_auto_exposure(device: &wgpu::Device) -> AutoExposure {
+        let histogram_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("luminance_histogram_buffer"),
+            size: 256 * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("auto_exposure_bind_group_layout"),
+            entries: &[
+                hdr_texture_entry(0, wgpu::ShaderStages::COMPUTE),
+                uniform_entry(1, wgpu::ShaderStages::COMPUTE),
+                storage_entry(2, wgpu::ShaderStages::COMPUTE, false),
+                storage_entry(3, wgpu::ShaderStages::COMPUTE, false),
+            ],
+        });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("luminance_histogram_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(include_str!(
+                "./shaders/luminance_histogram.wgsl"
+            ))),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("auto_exposure_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                module: &shader_module,
+                entry_point,
+            })
+        };
+
+        AutoExposure {
+            build_histogram_pipeline: create_pipeline("build_histogram"),
+            average_histogram_pipeline: create_pipeline("average_histogram"),
+            histogram_buffer,
+            bind_group_layout,
+        }
+    }
+}
+
+fn hdr_texture_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn uniform_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn storage_entry(
+    binding: u32,
+    visibility: wgpu::ShaderStages,
+    read_only: bool,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
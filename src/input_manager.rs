@@ -6,6 +6,7 @@ pub struct InputManager {
     just_pressed_mouse_buttons: Vec<MouseButton>,
     mouse_position: (f32, f32),
     mouse_delta: (f32, f32),
+    mouse_motion: (f32, f32),
 }
 
 impl InputManager {
@@ -17,6 +18,7 @@ impl InputManager {
             just_pressed_mouse_buttons: vec![],
             mouse_position: (0.0, 0.0),
             mouse_delta: (0.0, 0.0),
+            mouse_motion: (0.0, 0.0),
         }
     }
 
@@ -34,12 +36,21 @@ impl InputManager {
         }
     }
 
+    /// raw device input, unlike window events it keeps coming while the cursor is grabbed
+    pub fn update_device(&mut self, event: winit::event::DeviceEvent) {
+        if let winit::event::DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_motion.0 += delta.0 as f32;
+            self.mouse_motion.1 += delta.1 as f32
+        }
+    }
+
     // is neaded to be called after all main event are cleared and the application logic for this
     // update is completed
     pub fn clear(&mut self) {
         self.just_pressed_keys.clear();
         self.just_pressed_mouse_buttons.clear();
-        self.mouse_delta = (0.0, 0.0)
+        self.mouse_delta = (0.0, 0.0);
+        self.mouse_motion = (0.0, 0.0)
     }
 
     fn handle_keyboard_input(&mut self, input: winit::event::KeyboardInput) {
@@ -49,10 +60,8 @@ impl InputManager {
         if input.state == winit::event::ElementState::Pressed {
             let input: KeyCode = input.virtual_keycode.unwrap().into();
 
-            if self.pressed_keys.contains(&input) {
-                return;
-            } else {
-                self.just_pressed_keys.push(input.clone());
+            if !self.pressed_keys.contains(&input) {
+                self.just_pressed_keys.push(input);
                 self.pressed_keys.push(input);
             }
         } else {
@@ -73,10 +82,8 @@ impl InputManager {
         if state == winit::event::ElementState::Pressed {
             let input: MouseButton = input.into();
 
-            if self.pressed_mouse_buttons.contains(&input) {
-                return;
-            } else {
-                self.just_pressed_mouse_buttons.push(input.clone());
+            if !self.pressed_mouse_buttons.contains(&input) {
+                self.just_pressed_mouse_buttons.push(input);
                 self.pressed_mouse_buttons.push(input);
             }
         } else {
@@ -128,6 +135,18 @@ impl InputManager {
     pub fn mouse_delta(&self) -> (f32, f32) {
         self.mouse_delta
     }
+
+    /// raw mouse movement since the last `clear`, not affected by pointer acceleration or the
+    /// window border
+    pub fn mouse_motion(&self) -> (f32, f32) {
+        self.mouse_motion
+    }
+}
+
+impl Default for InputManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, Copy)]
@@ -0,0 +1,25 @@
+pub mod app;
+pub mod camera;
+pub mod camera_path;
+pub mod clock;
+pub mod culling;
+pub mod fog;
+pub mod hdr;
+pub mod input_manager;
+pub mod light;
+pub mod model;
+pub mod picking;
+pub mod post_process;
+pub mod profiler;
+pub mod render_graph;
+pub mod renderer;
+pub mod sky;
+pub mod skybox;
+pub mod ssao;
+pub mod terrain;
+#[cfg(test)]
+mod test_util;
+pub mod texture;
+pub mod timer;
+pub mod timing;
+pub mod tween;
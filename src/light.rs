@@ -0,0 +1,50 @@
+use cgmath::{InnerSpace, Vector3};
+
+/// single directional light, either the sun or the moon
+pub struct Light {
+    /// direction the light travels in, i.e. pointing away from the light source
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub ambient: Vector3<f32>,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            direction: Vector3::new(-0.3, -1.0, 0.2).normalize(),
+            color: Vector3::new(1.0, 1.0, 1.0),
+            ambient: Vector3::new(0.2, 0.2, 0.2),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    // w components are unused padding
+    direction: [f32; 4],
+    color: [f32; 4],
+    ambient: [f32; 4],
+}
+
+impl LightUniform {
+    pub fn new() -> Self {
+        Self {
+            direction: [0.0, -1.0, 0.0, 0.0],
+            color: [1.0; 4],
+            ambient: [0.0; 4],
+        }
+    }
+
+    pub fn update(&mut self, light: &Light) {
+        self.direction = light.direction.normalize().extend(0.0).into();
+        self.color = light.color.extend(0.0).into();
+        self.ambient = light.ambient.extend(0.0).into();
+    }
+}
+
+impl Default for LightUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
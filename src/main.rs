@@ -1,13 +1,4 @@
-mod app;
-mod camera;
-mod input_manager;
-mod model;
-mod renderer;
-mod texture;
-mod timer;
-mod timing;
-
-use app::App;
+use grass::app::App;
 
 #[tokio::main]
 async fn main() {
@@ -1,6 +1,8 @@
 use anyhow::anyhow;
 use bytemuck::{Pod, Zeroable};
 
+use crate::texture::AtlasRect;
+
 #[repr(C)]
 #[derive(Zeroable, Pod, Clone, Copy, Debug)]
 pub struct Vertex {
@@ -9,6 +11,10 @@ pub struct Vertex {
 }
 
 impl Vertex {
+    pub fn position(&self) -> [f32; 3] {
+        self.position
+    }
+
     pub fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
@@ -73,6 +79,16 @@ impl Mesh {
         Ok(Self { vertices, indices })
     }
 
+    /// maps the texture coordinates into `rect`, to sample one image of a texture atlas
+    pub fn with_atlas_rect(mut self, rect: &AtlasRect) -> Self {
+        for vertex in &mut self.vertices {
+            for i in 0..2 {
+                vertex.tex_coords[i] = rect.offset[i] + vertex.tex_coords[i] * rect.size[i];
+            }
+        }
+        self
+    }
+
     pub fn create_rectangle() -> Self {
         Self {
             vertices: vec![
@@ -0,0 +1,368 @@
+use cgmath::{InnerSpace, Point3, SquareMatrix, Vector3, Vector4};
+
+use crate::{camera::Camera, culling::Aabb, model::Mesh, terrain::HeightField};
+
+/// half line starting at `origin`, `direction` is normalized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    /// the ray through a pixel, e.g. `InputManager::mouse_position`, starting at the near
+    /// plane. works for every projection, orthographic rays are parallel.
+    pub fn from_screen(camera: &Camera, position: (f32, f32), size: (u32, u32)) -> Option<Self> {
+        let inverse = camera.build_view_projection_matrix().invert()?;
+        let x = position.0 / size.0.max(1) as f32 * 2.0 - 1.0;
+        let y = 1.0 - position.1 / size.1.max(1) as f32 * 2.0;
+        let far_depth = camera.far_depth();
+        let near_depth = 1.0 - far_depth;
+        // an infinite far plane can't be unprojected, so the direction is taken from halfway
+        let unproject = |depth: f32| {
+            let point = inverse * Vector4::new(x, y, depth, 1.0);
+            Point3::from_homogeneous(point)
+        };
+        let near = unproject(near_depth);
+        let halfway = unproject((near_depth + far_depth) / 2.0);
+        let direction = halfway - near;
+        (direction.magnitude2() > f32::EPSILON).then(|| Self::new(near, direction))
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// möller-trumbore, both sides of the triangle are hit. the normal faces the ray.
+    pub fn intersect_triangle(
+        &self,
+        a: Point3<f32>,
+        b: Point3<f32>,
+        c: Point3<f32>,
+    ) -> Option<(f32, Vector3<f32>)> {
+        let (ab, ac) = (b - a, c - a);
+        let p = self.direction.cross(ac);
+        let determinant = ab.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+        let offset = self.origin - a;
+        let u = offset.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = offset.cross(ab);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = ac.dot(q) * inverse;
+        if distance < 0.0 {
+            return None;
+        }
+        let normal = ab.cross(ac).normalize();
+        let normal = if normal.dot(self.direction) > 0.0 {
+            -normal
+        } else {
+            normal
+        };
+        Some((distance, normal))
+    }
+
+    /// closest triangle of the mesh
+    pub fn intersect_mesh(&self, mesh: &Mesh) -> Option<(f32, Vector3<f32>)> {
+        let position = |index: &u32| {
+            mesh.vertices
+                .get(*index as usize)
+                .map(|vertex| Point3::from(vertex.position()))
+        };
+        mesh.indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                let a = position(&triangle[0])?;
+                let b = position(&triangle[1])?;
+                let c = position(&triangle[2])?;
+                self.intersect_triangle(a, b, c)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    /// slab test, a ray starting inside the box hits it at distance 0
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<(f32, Vector3<f32>)> {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        let mut normal = -self.direction;
+        for axis in 0..3 {
+            let origin = self.origin[axis];
+            let direction = self.direction[axis];
+            if direction.abs() < f32::EPSILON {
+                if origin < aabb.min[axis] || origin > aabb.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let (mut entry, mut exit) = (
+                (aabb.min[axis] - origin) / direction,
+                (aabb.max[axis] - origin) / direction,
+            );
+            if entry > exit {
+                std::mem::swap(&mut entry, &mut exit)
+            }
+            if entry > near {
+                near = entry;
+                normal = Vector3::new(0.0, 0.0, 0.0);
+                normal[axis] = -direction.signum();
+            }
+            far = far.min(exit);
+            if near > far {
+                return None;
+            }
+        }
+        Some((near, normal))
+    }
+
+    /// marches along the ray in half cell steps up to `max_distance` and refines the first
+    /// crossing below the surface
+    pub fn intersect_height_field(
+        &self,
+        field: &HeightField,
+        max_distance: f32,
+    ) -> Option<(f32, Vector3<f32>)> {
+        let (min, max) = field.bounds();
+        let (lowest, highest) = field.height_range();
+        let bounds = Aabb::new(
+            Point3::new(min.0, lowest, min.1),
+            Point3::new(max.0, highest, max.1),
+        );
+        let start = self.intersect_aabb(&bounds)?.0;
+        if start > max_distance {
+            return None;
+        }
+        let above = |distance: f32| {
+            let point = self.at(distance);
+            point.y - field.height_at(point.x, point.z)
+        };
+        if above(start) < 0.0 {
+            // starts below the surface
+            return None;
+        }
+
+        let step = field.cell_size() * 0.5;
+        let mut previous = start;
+        let mut distance = start;
+        while distance < max_distance {
+            distance = (distance + step).min(max_distance);
+            if above(distance) <= 0.0 {
+                let (mut low, mut high) = (previous, distance);
+                for _ in 0..24 {
+                    let middle = (low + high) / 2.0;
+                    if above(middle) > 0.0 {
+                        low = middle
+                    } else {
+                        high = middle
+                    }
+                }
+                let point = self.at(high);
+                return Some((high, field.normal_at(point.x, point.z)));
+            }
+            previous = distance
+        }
+        None
+    }
+}
+
+/// identifies what a ray hit, chosen by whoever passes the objects to `pick`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectId(pub u32);
+
+/// something a ray can hit
+#[derive(Debug, Clone, Copy)]
+pub enum Pickable<'a> {
+    Mesh(&'a Mesh),
+    Aabb(Aabb),
+    /// only hit up to `max_distance` along the ray
+    HeightField {
+        field: &'a HeightField,
+        max_distance: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub object: ObjectId,
+    pub point: Point3<f32>,
+    /// faces the ray
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+/// the closest hit along `ray`
+pub fn pick<'a>(
+    ray: &Ray,
+    objects: impl IntoIterator<Item = (ObjectId, Pickable<'a>)>,
+) -> Option<Hit> {
+    objects
+        .into_iter()
+        .filter_map(|(object, pickable)| {
+            let (distance, normal) = match pickable {
+                Pickable::Mesh(mesh) => {
+                    // most rays miss, the bounds are much cheaper than every triangle
+                    ray.intersect_aabb(&Aabb::from_mesh(mesh)?)?;
+                    ray.intersect_mesh(mesh)?
+                }
+                Pickable::Aabb(aabb) => ray.intersect_aabb(&aabb)?,
+                Pickable::HeightField {
+                    field,
+                    max_distance,
+                } => ray.intersect_height_field(field, max_distance)?,
+            };
+            Some(Hit {
+                object,
+                point: ray.at(distance),
+                normal,
+                distance,
+            })
+        })
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::EuclideanSpace;
+
+    use super::*;
+    use crate::{camera::Projection, test_util::assert_close_vector};
+
+    fn camera() -> Camera {
+        Camera {
+            eye: Point3::new(0.0, 2.0, 5.0),
+            direction: -Vector3::unit_z(),
+            fovy: 60.0,
+            aspect: 2.0,
+            ..Camera::test_default()
+        }
+    }
+
+    #[test]
+    fn screen_center_looks_along_the_view_direction() {
+        let mut camera = camera();
+        for (infinite_far, reversed_z) in [(false, false), (true, true)] {
+            camera.infinite_far = infinite_far;
+            camera.reversed_z = reversed_z;
+            let ray = Ray::from_screen(&camera, (400.0, 200.0), (800, 400)).unwrap();
+            assert_close_vector(ray.direction, camera.direction);
+            assert_close_vector(ray.origin.to_vec(), Vector3::new(0.0, 2.0, 4.9));
+        }
+
+        camera.projection = Projection::Orthographic { height: 10.0 };
+        let ray = Ray::from_screen(&camera, (800.0, 0.0), (800, 400)).unwrap();
+        assert_close_vector(ray.direction, camera.direction);
+        assert_close_vector(ray.origin.to_vec(), Vector3::new(10.0, 7.0, 4.9));
+    }
+
+    #[test]
+    fn ray_hits_the_closest_triangle() {
+        let mesh = Mesh::create_rectangle();
+        let ray = Ray::new(Point3::new(0.5, 0.5, 3.0), -Vector3::unit_z());
+        let (distance, normal) = ray.intersect_mesh(&mesh).unwrap();
+        assert!((distance - 3.0).abs() < 1e-5);
+        assert_close_vector(normal, Vector3::unit_z());
+        let miss = Ray::new(Point3::new(1.5, 0.0, 3.0), -Vector3::unit_z());
+        assert!(miss.intersect_mesh(&mesh).is_none());
+        let behind = Ray::new(Point3::new(0.0, 0.0, 3.0), Vector3::unit_z());
+        assert!(behind.intersect_mesh(&mesh).is_none());
+    }
+
+    #[test]
+    fn ray_hits_box_faces() {
+        let aabb = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Point3::new(-5.0, 0.5, 0.0), Vector3::unit_x());
+        let (distance, normal) = ray.intersect_aabb(&aabb).unwrap();
+        assert!((distance - 4.0).abs() < 1e-5);
+        assert_close_vector(normal, -Vector3::unit_x());
+        let inside = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::unit_y());
+        assert_eq!(inside.intersect_aabb(&aabb).unwrap().0, 0.0);
+        let parallel = Ray::new(Point3::new(-5.0, 2.0, 0.0), Vector3::unit_x());
+        assert!(parallel.intersect_aabb(&aabb).is_none());
+    }
+
+    #[test]
+    fn ray_hits_the_height_field() {
+        let field = HeightField::from_fn((-10.0, -10.0), 1.0, 21, 21, |x, _| x * 0.5);
+        let ray = Ray::new(Point3::new(0.0, 10.0, 0.0), -Vector3::unit_y());
+        let (distance, normal) = ray.intersect_height_field(&field, 100.0).unwrap();
+        assert!((distance - 10.0).abs() < 1e-3);
+        assert_close_vector(normal, Vector3::new(-0.5, 1.0, 0.0).normalize());
+        assert!(ray.intersect_height_field(&field, 5.0).is_none());
+    }
+
+    #[test]
+    fn pick_returns_the_nearest_object() {
+        let mesh = Mesh::create_rectangle();
+        let ground = HeightField::flat(100.0, -3.0);
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, -0.1, -1.0));
+        let objects = [
+            (
+                ObjectId(0),
+                Pickable::HeightField {
+                    field: &ground,
+                    max_distance: 100.0,
+                },
+            ),
+            (ObjectId(1), Pickable::Mesh(&mesh)),
+        ];
+        let hit = pick(&ray, objects).unwrap();
+        assert_eq!(hit.object, ObjectId(1));
+        assert!((hit.point.z).abs() < 1e-4);
+
+        let down = Ray::new(Point3::new(5.0, 0.0, 5.0), -Vector3::unit_y());
+        let hit = pick(&down, objects).unwrap();
+        assert_eq!(hit.object, ObjectId(0));
+        assert!((hit.point.y + 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn pick_mixes_boxes_and_meshes() {
+        let mesh = Mesh::create_rectangle();
+        let in_front = Aabb::new(Point3::new(0.5, -0.5, 1.0), Point3::new(1.5, 0.5, 2.0));
+        let behind = Aabb::new(Point3::new(-2.0, -2.0, -3.0), Point3::new(2.0, 2.0, -2.0));
+        let objects = [
+            (ObjectId(0), Pickable::Aabb(behind)),
+            (ObjectId(1), Pickable::Mesh(&mesh)),
+            (ObjectId(2), Pickable::Aabb(in_front)),
+        ];
+
+        let hit = pick(
+            &Ray::new(Point3::new(0.8, 0.0, 5.0), -Vector3::unit_z()),
+            objects,
+        )
+        .unwrap();
+        assert_eq!(hit.object, ObjectId(2));
+        assert_close_vector(hit.point.to_vec(), Vector3::new(0.8, 0.0, 2.0));
+        assert_close_vector(hit.normal, Vector3::unit_z());
+
+        let hit = pick(
+            &Ray::new(Point3::new(-0.5, 0.0, 5.0), -Vector3::unit_z()),
+            objects,
+        )
+        .unwrap();
+        assert_eq!(hit.object, ObjectId(1));
+        assert!((hit.distance - 5.0).abs() < 1e-4);
+
+        let hit = pick(
+            &Ray::new(Point3::new(1.8, 0.0, 5.0), -Vector3::unit_z()),
+            objects,
+        )
+        .unwrap();
+        assert_eq!(hit.object, ObjectId(0));
+        assert!((hit.distance - 7.0).abs() < 1e-4);
+    }
+}
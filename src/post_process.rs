@@ -0,0 +1,806 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    hdr::HDR_FORMAT,
+    render_graph::{PassContext, RenderGraph, ResourceHandle, TransientTexture},
+    texture::{Texture, TextureFormat, TextureOptions},
+};
+
+/// format of the targets between effects, holding linear tonemapped colors
+pub const POST_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+const SHADER_SOURCE: &str = concat!(
+    include_str!("./shaders/common.wgsl"),
+    include_str!("./shaders/post_process.wgsl")
+);
+
+const MAX_BLOOM_MIPS: u32 = 6;
+
+const IDENTITY_LUT_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostEffect {
+    Bloom,
+    ColorGrading,
+    Vignette,
+    FilmGrain,
+    Sharpen,
+}
+
+impl PostEffect {
+    /// bloom works on scene referred values and runs on the hdr target before tonemapping,
+    /// every other effect runs on the tonemapped image
+    pub fn before_tonemapping(&self) -> bool {
+        matches!(self, PostEffect::Bloom)
+    }
+}
+
+/// post effects in the order they run in, each with whether it is enabled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectChain {
+    effects: Vec<(PostEffect, bool)>,
+}
+
+impl EffectChain {
+    /// effects listed more than once only keep their first slot
+    pub fn new(effects: &[(PostEffect, bool)]) -> Self {
+        let mut chain = Self { effects: vec![] };
+        for &(effect, enabled) in effects {
+            if !chain.contains(effect) {
+                chain.effects.push((effect, enabled))
+            }
+        }
+        chain
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (PostEffect, bool)> + '_ {
+        self.effects.iter().copied()
+    }
+
+    /// enabled effects in the order they run in
+    pub fn enabled(&self) -> impl Iterator<Item = PostEffect> + '_ {
+        self.iter()
+            .filter_map(|(effect, enabled)| enabled.then_some(effect))
+    }
+
+    pub fn contains(&self, effect: PostEffect) -> bool {
+        self.effects.iter().any(|(other, _)| *other == effect)
+    }
+
+    pub fn is_enabled(&self, effect: PostEffect) -> bool {
+        self.effects
+            .iter()
+            .any(|(other, enabled)| *other == effect && *enabled)
+    }
+
+    pub fn set_enabled(&mut self, effect: PostEffect, enabled: bool) {
+        if let Some((_, slot)) = self.effects.iter_mut().find(|(other, _)| *other == effect) {
+            *slot = enabled
+        }
+    }
+
+    pub fn toggle(&mut self, effect: PostEffect) {
+        self.set_enabled(effect, !self.is_enabled(effect))
+    }
+
+    /// moves the given effects to the front in the given order, the remaining ones keep their
+    /// relative order behind them. effects listed more than once only count the first time,
+    /// effects missing from the chain are ignored.
+    pub fn set_order(&mut self, order: &[PostEffect]) {
+        let mut effects = Vec::with_capacity(self.effects.len());
+        for effect in order.iter().copied() {
+            if effects.iter().any(|(other, _)| *other == effect) {
+                continue;
+            }
+            if let Some(slot) = self.effects.iter().find(|(other, _)| *other == effect) {
+                effects.push(*slot)
+            }
+        }
+        for slot in &self.effects {
+            if !effects.iter().any(|(effect, _)| *effect == slot.0) {
+                effects.push(*slot)
+            }
+        }
+        self.effects = effects
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostProcessUniform {
+    bloom_threshold: f32,
+    bloom_knee: f32,
+    color_grading_contribution: f32,
+    lut_size: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    vignette_smoothness: f32,
+    film_grain_intensity: f32,
+    sharpen_strength: f32,
+    time: f32,
+    encode_srgb: u32,
+    _padding: u32,
+}
+
+/// chain of fullscreen effects running after the main pass. effects run in the order they are
+/// listed in, bloom on the hdr target before tonemapping and the rest on the tonemapped image
+/// before the result is copied into the surface.
+pub struct PostProcess {
+    /// brightness above which pixels start to bloom
+    pub bloom_threshold: f32,
+    /// fraction of the threshold over which bloom fades in
+    pub bloom_knee: f32,
+    pub bloom_intensity: f32,
+    /// blend between the ungraded and graded image
+    pub color_grading_contribution: f32,
+    pub vignette_intensity: f32,
+    /// distance from the center at which the vignette starts, relative to the screen height
+    pub vignette_radius: f32,
+    pub vignette_smoothness: f32,
+    pub film_grain_intensity: f32,
+    pub sharpen_strength: f32,
+    effects: EffectChain,
+    uniform: PostProcessUniform,
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    lut_bind_group_layout: wgpu::BindGroupLayout,
+    lut: Texture,
+    lut_bind_group: wgpu::BindGroup,
+    bloom_prefilter_pipeline: wgpu::RenderPipeline,
+    bloom_downsample_pipeline: wgpu::RenderPipeline,
+    bloom_upsample_pipeline: wgpu::RenderPipeline,
+    bloom_composite_pipeline: wgpu::RenderPipeline,
+    color_grading_pipeline: wgpu::RenderPipeline,
+    vignette_pipeline: wgpu::RenderPipeline,
+    film_grain_pipeline: wgpu::RenderPipeline,
+    sharpen_pipeline: wgpu::RenderPipeline,
+    output_pipeline: wgpu::RenderPipeline,
+}
+
+impl PostProcess {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> Self {
+        let uniform = PostProcessUniform {
+            bloom_threshold: 1.0,
+            bloom_knee: 0.5,
+            color_grading_contribution: 1.0,
+            lut_size: IDENTITY_LUT_SIZE as f32,
+            vignette_intensity: 0.35,
+            vignette_radius: 0.45,
+            vignette_smoothness: 0.5,
+            film_grain_intensity: 0.05,
+            sharpen_strength: 0.15,
+            time: 0.0,
+            encode_srgb: (!config.format.describe().srgb) as u32,
+            _padding: 0,
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("post_process_buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_process_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let lut_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("lut_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let lut = Texture::from_lut(
+            device,
+            queue,
+            &Self::lut_options(),
+            &image::DynamicImage::ImageRgba8(identity_lut(IDENTITY_LUT_SIZE)),
+        )
+        .expect("identity lut has a valid layout");
+        let lut_bind_group = Self::create_lut_bind_group(device, &lut_bind_group_layout, &lut);
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("post_process_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(SHADER_SOURCE)),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post_process_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let lut_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("color_grading_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout, &lut_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+        // scaled by the bloom intensity through the blend constant
+        let composite = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Constant,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+        let create_pipeline = |layout, entry_point, format, blend| {
+            Self::create_pipeline(device, layout, &shader_module, entry_point, format, blend)
+        };
+
+        Self {
+            bloom_threshold: uniform.bloom_threshold,
+            bloom_knee: uniform.bloom_knee,
+            bloom_intensity: 0.05,
+            color_grading_contribution: uniform.color_grading_contribution,
+            vignette_intensity: uniform.vignette_intensity,
+            vignette_radius: uniform.vignette_radius,
+            vignette_smoothness: uniform.vignette_smoothness,
+            film_grain_intensity: uniform.film_grain_intensity,
+            sharpen_strength: uniform.sharpen_strength,
+            effects: EffectChain::new(&[
+                (PostEffect::Bloom, true),
+                (PostEffect::ColorGrading, true),
+                (PostEffect::Sharpen, false),
+                (PostEffect::Vignette, true),
+                (PostEffect::FilmGrain, false),
+            ]),
+            bloom_prefilter_pipeline: create_pipeline(
+                &layout,
+                "fs_bloom_prefilter",
+                HDR_FORMAT,
+                None,
+            ),
+            bloom_downsample_pipeline: create_pipeline(
+                &layout,
+                "fs_bloom_downsample",
+                HDR_FORMAT,
+                None,
+            ),
+            bloom_upsample_pipeline: create_pipeline(
+                &layout,
+                "fs_bloom_upsample",
+                HDR_FORMAT,
+                Some(additive),
+            ),
+            bloom_composite_pipeline: create_pipeline(
+                &layout,
+                "fs_bloom_upsample",
+                HDR_FORMAT,
+                Some(composite),
+            ),
+            color_grading_pipeline: create_pipeline(
+                &lut_layout,
+                "fs_color_grading",
+                POST_FORMAT,
+                None,
+            ),
+            vignette_pipeline: create_pipeline(&layout, "fs_vignette", POST_FORMAT, None),
+            film_grain_pipeline: create_pipeline(&layout, "fs_film_grain", POST_FORMAT, None),
+            sharpen_pipeline: create_pipeline(&layout, "fs_sharpen", POST_FORMAT, None),
+            output_pipeline: create_pipeline(&layout, "fs_output", config.format, None),
+            uniform,
+            buffer,
+            bind_group_layout,
+            lut_bind_group_layout,
+            lut,
+            lut_bind_group,
+        }
+    }
+
+    pub fn effects(&self) -> &EffectChain {
+        &self.effects
+    }
+
+    pub fn effects_mut(&mut self) -> &mut EffectChain {
+        &mut self.effects
+    }
+
+    /// replaces the color grading lut, see `Texture::from_lut` for the expected layout
+    pub fn set_lut(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+    ) -> anyhow::Result<()> {
+        self.lut = Texture::from_lut(device, queue, &Self::lut_options(), image)?;
+        self.lut_bind_group =
+            Self::create_lut_bind_group(device, &self.lut_bind_group_layout, &self.lut);
+        self.uniform.lut_size = image.height() as f32;
+        Ok(())
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, time: std::time::Duration) {
+        self.uniform.bloom_threshold = self.bloom_threshold;
+        self.uniform.bloom_knee = self.bloom_knee;
+        self.uniform.color_grading_contribution = self.color_grading_contribution;
+        self.uniform.vignette_intensity = self.vignette_intensity;
+        self.uniform.vignette_radius = self.vignette_radius;
+        self.uniform.vignette_smoothness = self.vignette_smoothness;
+        self.uniform.film_grain_intensity = self.film_grain_intensity;
+        self.uniform.sharpen_strength = self.sharpen_strength;
+        self.uniform.time = time.as_secs_f32();
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    /// adds the effects working on the hdr target, which have to run before tonemapping.
+    /// returns the hdr target after them.
+    pub fn add_before_tonemapping<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        mut hdr: ResourceHandle,
+        surface_size: (u32, u32),
+    ) -> ResourceHandle {
+        for effect in self
+            .effects
+            .enabled()
+            .filter(|effect| effect.before_tonemapping())
+        {
+            if effect == PostEffect::Bloom {
+                hdr = self.add_bloom(graph, hdr, surface_size)
+            }
+        }
+        hdr
+    }
+
+    /// adds the effects working on the tonemapped `input` and a final pass copying the result
+    /// into the surface. returns the written surface.
+    pub fn add_after_tonemapping<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        mut input: ResourceHandle,
+        surface: ResourceHandle,
+    ) -> ResourceHandle {
+        for effect in self
+            .effects
+            .enabled()
+            .filter(|effect| !effect.before_tonemapping())
+        {
+            let (pipeline, lut_bind_group) = match effect {
+                PostEffect::ColorGrading => {
+                    (&self.color_grading_pipeline, Some(&self.lut_bind_group))
+                }
+                PostEffect::Vignette => (&self.vignette_pipeline, None),
+                PostEffect::FilmGrain => (&self.film_grain_pipeline, None),
+                PostEffect::Sharpen => (&self.sharpen_pipeline, None),
+                PostEffect::Bloom => unreachable!("bloom runs before tonemapping"),
+            };
+            // a new target per effect, the graph aliases them into a ping-pong pair
+            let output = graph.create_texture(TransientTexture::new("post_process", POST_FORMAT));
+            let mut pass = graph.add_pass("post_process_pass");
+            let source = pass.read(input);
+            let output = pass.write(output);
+            pass.execute(move |context| {
+                let bind_group = self.create_source_bind_group(context, source);
+                let mut render_pass = Self::begin_pass(
+                    context.encoder,
+                    "post_process_pass",
+                    context.resources.view(output),
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                );
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                if let Some(lut_bind_group) = lut_bind_group {
+                    render_pass.set_bind_group(1, lut_bind_group, &[]);
+                }
+                render_pass.draw(0..3, 0..1);
+            });
+            input = output;
+        }
+
+        let mut pass = graph.add_pass("post_process_output_pass");
+        pass.read(input);
+        let surface = pass.write(surface);
+        pass.execute(move |context| {
+            let bind_group = self.create_source_bind_group(context, input);
+            let mut render_pass = Self::begin_pass(
+                context.encoder,
+                "post_process_output_pass",
+                context.resources.view(surface),
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            );
+            render_pass.set_pipeline(&self.output_pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        });
+        surface
+    }
+
+    /// downsamples the hdr target through the bloom mip chain, then upsamples back up adding
+    /// each level onto the next larger one and finally onto the hdr target
+    fn add_bloom<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        hdr: ResourceHandle,
+        (width, height): (u32, u32),
+    ) -> ResourceHandle {
+        let descriptor = TransientTexture::new("bloom", HDR_FORMAT).size_divisor(2);
+        let (width, height) = descriptor.size(width, height);
+        let mip_count = (u32::BITS - width.min(height).leading_zeros()).clamp(1, MAX_BLOOM_MIPS);
+        let bloom = graph.create_texture(descriptor.mip_level_count(mip_count));
+
+        let mut pass = graph.add_pass("bloom_pass");
+        pass.read(hdr);
+        let bloom = pass.write(bloom);
+        pass.execute(move |context| {
+            let texture = context.resources.texture(bloom);
+            let mip_views: Vec<_> = (0..mip_count)
+                .map(|mip| {
+                    texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                        base_mip_level: mip,
+                        mip_level_count: std::num::NonZeroU32::new(1),
+                        ..Default::default()
+                    })
+                })
+                .collect();
+            let mip_bind_groups: Vec<_> = mip_views
+                .iter()
+                .map(|view| {
+                    Self::create_bind_group(
+                        context.device,
+                        &self.bind_group_layout,
+                        &self.buffer,
+                        view,
+                        &texture.sampler,
+                    )
+                })
+                .collect();
+            let hdr_bind_group = self.create_source_bind_group(context, hdr);
+
+            for (mip, view) in mip_views.iter().enumerate() {
+                let (pipeline, bind_group) = if mip == 0 {
+                    (&self.bloom_prefilter_pipeline, &hdr_bind_group)
+                } else {
+                    (&self.bloom_downsample_pipeline, &mip_bind_groups[mip - 1])
+                };
+                let mut render_pass = Self::begin_pass(
+                    context.encoder,
+                    "bloom_downsample_pass",
+                    view,
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                );
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+            for mip in (1..mip_views.len()).rev() {
+                let mut render_pass = Self::begin_pass(
+                    context.encoder,
+                    "bloom_upsample_pass",
+                    &mip_views[mip - 1],
+                    wgpu::LoadOp::Load,
+                );
+                render_pass.set_pipeline(&self.bloom_upsample_pipeline);
+                render_pass.set_bind_group(0, &mip_bind_groups[mip], &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+        });
+
+        let mut pass = graph.add_pass("bloom_composite_pass");
+        pass.read(bloom);
+        let hdr = pass.write(hdr);
+        pass.execute(move |context| {
+            let texture = context.resources.texture(bloom);
+            let mip_view = texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            });
+            let bind_group = Self::create_bind_group(
+                context.device,
+                &self.bind_group_layout,
+                &self.buffer,
+                &mip_view,
+                &texture.sampler,
+            );
+            let mut render_pass = Self::begin_pass(
+                context.encoder,
+                "bloom_composite_pass",
+                context.resources.view(hdr),
+                wgpu::LoadOp::Load,
+            );
+            render_pass.set_pipeline(&self.bloom_composite_pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            let intensity = self.bloom_intensity as f64;
+            render_pass.set_blend_constant(wgpu::Color {
+                r: intensity,
+                g: intensity,
+                b: intensity,
+                a: 1.0,
+            });
+            render_pass.draw(0..3, 0..1);
+        });
+        hdr
+    }
+
+    fn begin_pass<'a>(
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        label: &str,
+        view: &'a wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPass<'a> {
+        command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        })
+    }
+
+    fn lut_options() -> TextureOptions {
+        TextureOptions::new()
+            .label("color_grading_lut")
+            .format(TextureFormat::Rgba8Linear)
+    }
+
+    fn create_lut_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        lut: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lut_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&lut.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&lut.sampler),
+                },
+            ],
+        })
+    }
+
+    /// bind group sampling a texture of the graph
+    fn create_source_bind_group(
+        &self,
+        context: &PassContext,
+        source: ResourceHandle,
+    ) -> wgpu::BindGroup {
+        let texture = context.resources.texture(source);
+        Self::create_bind_group(
+            context.device,
+            &self.bind_group_layout,
+            &self.buffer,
+            &texture.view,
+            &texture.sampler,
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post_process_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader_module: &wgpu::ShaderModule,
+        entry_point: &str,
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: shader_module,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        })
+    }
+}
+
+/// lut which maps every color onto itself
+pub fn identity_lut(size: u32) -> image::RgbaImage {
+    let scale = 255.0 / (size - 1) as f32;
+    image::RgbaImage::from_fn(size * size, size, |x, y| {
+        let (r, g, b) = (x % size, y, x / size);
+        image::Rgba([
+            (r as f32 * scale).round() as u8,
+            (g as f32 * scale).round() as u8,
+            (b as f32 * scale).round() as u8,
+            255,
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain() -> EffectChain {
+        EffectChain::new(&[
+            (PostEffect::Bloom, true),
+            (PostEffect::ColorGrading, true),
+            (PostEffect::Sharpen, false),
+            (PostEffect::Vignette, true),
+        ])
+    }
+
+    fn order(chain: &EffectChain) -> Vec<PostEffect> {
+        chain.iter().map(|(effect, _)| effect).collect()
+    }
+
+    #[test]
+    fn set_order_moves_the_given_effects_to_the_front() {
+        let mut chain = chain();
+        chain.set_order(&[PostEffect::Vignette, PostEffect::Sharpen]);
+        assert_eq!(
+            order(&chain),
+            [
+                PostEffect::Vignette,
+                PostEffect::Sharpen,
+                PostEffect::Bloom,
+                PostEffect::ColorGrading
+            ]
+        );
+    }
+
+    #[test]
+    fn set_order_skips_repeated_and_missing_effects() {
+        let mut chain = chain();
+        chain.set_order(&[
+            PostEffect::Sharpen,
+            PostEffect::FilmGrain,
+            PostEffect::Bloom,
+            PostEffect::Sharpen,
+        ]);
+        assert_eq!(
+            order(&chain),
+            [
+                PostEffect::Sharpen,
+                PostEffect::Bloom,
+                PostEffect::ColorGrading,
+                PostEffect::Vignette
+            ]
+        );
+    }
+
+    #[test]
+    fn set_order_keeps_whether_effects_are_enabled() {
+        let mut chain = chain();
+        chain.set_order(&[PostEffect::Sharpen, PostEffect::Vignette]);
+        assert!(!chain.is_enabled(PostEffect::Sharpen));
+        assert_eq!(
+            chain.enabled().collect::<Vec<_>>(),
+            [
+                PostEffect::Vignette,
+                PostEffect::Bloom,
+                PostEffect::ColorGrading
+            ]
+        );
+    }
+
+    #[test]
+    fn new_keeps_the_first_slot_of_repeated_effects() {
+        let chain = EffectChain::new(&[
+            (PostEffect::Vignette, false),
+            (PostEffect::Bloom, true),
+            (PostEffect::Vignette, true),
+        ]);
+        assert_eq!(order(&chain), [PostEffect::Vignette, PostEffect::Bloom]);
+        assert!(!chain.is_enabled(PostEffect::Vignette));
+    }
+
+    #[test]
+    fn toggle_flips_only_the_given_effect() {
+        let mut chain = chain();
+        chain.toggle(PostEffect::Sharpen);
+        chain.toggle(PostEffect::Bloom);
+        assert!(chain.is_enabled(PostEffect::Sharpen));
+        assert!(!chain.is_enabled(PostEffect::Bloom));
+        assert!(chain.is_enabled(PostEffect::ColorGrading));
+
+        // effects missing from the chain can't be enabled
+        chain.toggle(PostEffect::FilmGrain);
+        assert!(!chain.is_enabled(PostEffect::FilmGrain));
+        assert!(!chain.contains(PostEffect::FilmGrain));
+    }
+
+    #[test]
+    fn identity_lut_maps_colors_onto_themselves() {
+        let lut = identity_lut(4);
+        assert_eq!(lut.dimensions(), (16, 4));
+        // red runs along each slice, green down the rows and blue across the slices
+        assert_eq!(lut.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(lut.get_pixel(3, 0).0, [255, 0, 0, 255]);
+        assert_eq!(lut.get_pixel(0, 3).0, [0, 255, 0, 255]);
+        assert_eq!(lut.get_pixel(12, 0).0, [0, 0, 255, 255]);
+        assert_eq!(lut.get_pixel(6, 1).0, [170, 85, 85, 255]);
+    }
+}
@@ -0,0 +1,538 @@
+use std::{
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// timestamps written per frame are limited to two per scope
+const MAX_SCOPES: u32 = 64;
+
+/// frames whose timestamps can be in flight at once, frames beyond that aren't profiled
+const READBACK_FRAMES: usize = 4;
+
+/// gpu scopes are put onto their own track in the trace
+const GPU_THREAD_ID: u64 = 0;
+
+#[derive(Debug, Clone)]
+struct TraceEvent {
+    name: String,
+    thread_id: u64,
+    start: Duration,
+    duration: Duration,
+}
+
+/// collects cpu and gpu scopes while capturing and writes them as a chrome trace, which can be
+/// opened in chrome://tracing or perfetto
+#[derive(Clone)]
+pub struct TraceRecorder {
+    start: Instant,
+    capturing: Arc<AtomicBool>,
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+impl TraceRecorder {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            capturing: Arc::new(AtomicBool::new(false)),
+            events: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.load(Ordering::Relaxed)
+    }
+
+    /// drops the events of the previous capture
+    pub fn start_capture(&self) {
+        self.events.lock().unwrap().clear();
+        self.capturing.store(true, Ordering::Relaxed)
+    }
+
+    pub fn stop_capture(&self) {
+        self.capturing.store(false, Ordering::Relaxed)
+    }
+
+    /// time since the recorder was created, which is the time base of the trace
+    pub fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn write_chrome_trace(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.chrome_trace())?;
+        Ok(())
+    }
+
+    /// the captured events in the chrome trace event format
+    pub fn chrome_trace(&self) -> String {
+        let events = self.events.lock().unwrap();
+        let mut json = String::from("{\"traceEvents\":[\n");
+        let _ = write!(
+            json,
+            "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"gpu\"}}}}",
+            GPU_THREAD_ID
+        );
+        for event in events.iter() {
+            let _ = write!(
+                json,
+                ",\n{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":{}}}",
+                escape_json(&event.name),
+                if event.thread_id == GPU_THREAD_ID {
+                    "gpu"
+                } else {
+                    "cpu"
+                },
+                event.start.as_secs_f64() * 1e6,
+                event.duration.as_secs_f64() * 1e6,
+                event.thread_id
+            );
+        }
+        json.push_str("\n]}\n");
+        json
+    }
+
+    fn record(&self, event: TraceEvent) {
+        if self.is_capturing() {
+            self.events.lock().unwrap().push(event)
+        }
+    }
+}
+
+impl Default for TraceRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// records the time spent in every entered span into a `TraceRecorder`. spans with a `label`
+/// field are named after it instead of the span name.
+pub struct TraceLayer {
+    recorder: TraceRecorder,
+}
+
+impl TraceLayer {
+    pub fn new(recorder: TraceRecorder) -> Self {
+        Self { recorder }
+    }
+}
+
+struct SpanTiming {
+    name: String,
+    entered: Option<Duration>,
+}
+
+struct LabelVisitor(Option<String>);
+
+impl Visit for LabelVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "label" {
+            self.0 = Some(value.to_string())
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "label" {
+            self.0 = Some(format!("{:?}", value))
+        }
+    }
+}
+
+impl<S> Layer<S> for TraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+        let mut visitor = LabelVisitor(None);
+        attributes.record(&mut visitor);
+        let name = visitor
+            .0
+            .unwrap_or_else(|| attributes.metadata().name().to_string());
+        if let Some(span) = context.span(id) {
+            span.extensions_mut().insert(SpanTiming {
+                name,
+                entered: None,
+            });
+        }
+    }
+
+    fn on_enter(&self, id: &Id, context: Context<'_, S>) {
+        if !self.recorder.is_capturing() {
+            return;
+        }
+        if let Some(span) = context.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                timing.entered = Some(self.recorder.now())
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, context: Context<'_, S>) {
+        let Some(span) = context.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(timing) = extensions.get_mut::<SpanTiming>() else {
+            return;
+        };
+        if let Some(entered) = timing.entered.take() {
+            self.recorder.record(TraceEvent {
+                name: timing.name.clone(),
+                thread_id: thread_id(),
+                start: entered,
+                duration: self.recorder.now().saturating_sub(entered),
+            })
+        }
+    }
+}
+
+/// gpu time spent in a scope of the last profiled frame
+#[derive(Debug, Clone, Copy)]
+pub struct ScopeTiming {
+    pub label: &'static str,
+    pub duration: Duration,
+}
+
+type MapResult = Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>;
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    labels: Vec<&'static str>,
+    /// recorder time at which the frame was submitted, which gpu scopes are placed relative to
+    submitted: Duration,
+    /// none while the buffer is free to be written
+    map_result: Option<MapResult>,
+}
+
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    /// nanoseconds per tick
+    period: f32,
+    readbacks: Vec<Readback>,
+    /// readback of the frame being recorded, none if every readback is still in flight
+    current: Option<usize>,
+}
+
+/// measures the gpu time of scopes with timestamp queries. the results are read back
+/// asynchronously a few frames later. does nothing if timestamp queries aren't supported.
+#[derive(Default)]
+pub struct GpuProfiler {
+    timestamps: Option<Timestamps>,
+    last_frame: Vec<ScopeTiming>,
+    recorder: Option<TraceRecorder>,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            tracing::info!("timestamp queries are not supported, gpu profiling is disabled");
+            return Self::default();
+        }
+        let size = (MAX_SCOPES * 2) as wgpu::BufferAddress
+            * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("profiler_query_set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_SCOPES * 2,
+        });
+        let readbacks = (0..READBACK_FRAMES)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("profiler_readback_buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                labels: vec![],
+                submitted: Duration::ZERO,
+                map_result: None,
+            })
+            .collect();
+
+        Self {
+            timestamps: Some(Timestamps {
+                query_set,
+                period: queue.get_timestamp_period(),
+                readbacks,
+                current: None,
+            }),
+            last_frame: vec![],
+            recorder: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.timestamps.is_some()
+    }
+
+    /// gpu scopes get added to the trace of the recorder while it is capturing
+    pub fn set_recorder(&mut self, recorder: Option<TraceRecorder>) {
+        self.recorder = recorder
+    }
+
+    /// the most recent frame whose timestamps were read back
+    pub fn last_frame(&self) -> &[ScopeTiming] {
+        &self.last_frame
+    }
+
+    pub fn begin_frame(&mut self) {
+        let Some(timestamps) = &mut self.timestamps else {
+            return;
+        };
+        timestamps.current = timestamps
+            .readbacks
+            .iter()
+            .position(|readback| readback.map_result.is_none());
+        if let Some(current) = timestamps.current {
+            timestamps.readbacks[current].labels.clear()
+        }
+    }
+
+    /// returns the scope to pass to `end_scope`, none if the frame isn't profiled
+    pub fn begin_scope(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: &'static str,
+    ) -> Option<u32> {
+        let timestamps = self.timestamps.as_mut()?;
+        let labels = &mut timestamps.readbacks[timestamps.current?].labels;
+        if labels.len() as u32 >= MAX_SCOPES {
+            return None;
+        }
+        let scope = labels.len() as u32;
+        labels.push(label);
+        encoder.write_timestamp(&timestamps.query_set, scope * 2);
+        Some(scope)
+    }
+
+    pub fn end_scope(&mut self, encoder: &mut wgpu::CommandEncoder, scope: Option<u32>) {
+        if let (Some(timestamps), Some(scope)) = (&self.timestamps, scope) {
+            encoder.write_timestamp(&timestamps.query_set, scope * 2 + 1)
+        }
+    }
+
+    /// resolves the timestamps of the frame into its readback buffer, has to be recorded after
+    /// the last scope
+    pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(timestamps) = &self.timestamps else {
+            return;
+        };
+        let Some(readback) = timestamps
+            .current
+            .map(|current| &timestamps.readbacks[current])
+        else {
+            return;
+        };
+        let count = readback.labels.len() as u32 * 2;
+        if count == 0 {
+            return;
+        }
+        encoder.resolve_query_set(&timestamps.query_set, 0..count, &readback.buffer, 0);
+    }
+
+    /// has to be called once the frame was submitted. maps its readback buffer and reads back
+    /// the timestamps of earlier frames which finished in the meantime.
+    pub fn frame_submitted(&mut self, device: &wgpu::Device) {
+        let submitted = self
+            .recorder
+            .as_ref()
+            .map_or(Duration::ZERO, |recorder| recorder.now());
+        if let Some(timestamps) = &mut self.timestamps {
+            if let Some(current) = timestamps.current.take() {
+                let readback = &mut timestamps.readbacks[current];
+                if !readback.labels.is_empty() {
+                    let map_result: MapResult = Arc::new(Mutex::new(None));
+                    let callback_result = map_result.clone();
+                    readback
+                        .buffer
+                        .slice(..)
+                        .map_async(wgpu::MapMode::Read, move |result| {
+                            *callback_result.lock().unwrap() = Some(result)
+                        });
+                    readback.submitted = submitted;
+                    readback.map_result = Some(map_result);
+                }
+            }
+        }
+        device.poll(wgpu::Maintain::Poll);
+        self.collect();
+    }
+
+    fn collect(&mut self) {
+        let Some(timestamps) = &mut self.timestamps else {
+            return;
+        };
+        // oldest frames first, so the last frame ends up being the most recent one
+        let mut finished: Vec<_> = timestamps
+            .readbacks
+            .iter_mut()
+            .filter(|readback| {
+                readback
+                    .map_result
+                    .as_ref()
+                    .is_some_and(|result| result.lock().unwrap().is_some())
+            })
+            .collect();
+        finished.sort_by_key(|readback| readback.submitted);
+        for readback in finished {
+            let result = readback
+                .map_result
+                .take()
+                .and_then(|result| result.lock().unwrap().take());
+            if let Some(Err(err)) = result {
+                tracing::warn!("failed to read back gpu timestamps: {}", err);
+                continue;
+            }
+            {
+                let data = readback.buffer.slice(..).get_mapped_range();
+                let ticks: &[u64] = bytemuck::cast_slice(&data[..readback.labels.len() * 16]);
+                let to_duration = |ticks: u64| {
+                    Duration::from_nanos((ticks as f64 * timestamps.period as f64) as u64)
+                };
+                let base = ticks.iter().step_by(2).copied().min().unwrap_or(0);
+                self.last_frame = readback
+                    .labels
+                    .iter()
+                    .zip(ticks.chunks_exact(2))
+                    .map(|(label, ticks)| ScopeTiming {
+                        label,
+                        duration: to_duration(ticks[1].saturating_sub(ticks[0])),
+                    })
+                    .collect();
+                if let Some(recorder) = &self.recorder {
+                    // gpu and cpu clocks aren't synchronized, scopes are placed relative to
+                    // the submission of their frame
+                    for (label, ticks) in readback.labels.iter().zip(ticks.chunks_exact(2)) {
+                        recorder.record(TraceEvent {
+                            name: label.to_string(),
+                            thread_id: GPU_THREAD_ID,
+                            start: readback.submitted + to_duration(ticks[0].saturating_sub(base)),
+                            duration: to_duration(ticks[1].saturating_sub(ticks[0])),
+                        })
+                    }
+                }
+            }
+            readback.buffer.unmap();
+        }
+    }
+}
+
+/// ids of cpu threads in the trace, starting after the gpu track
+fn thread_id() -> u64 {
+    static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(GPU_THREAD_ID + 1);
+    thread_local! {
+        static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+    }
+    THREAD_ID.with(|id| *id)
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    fn event(name: &str, thread_id: u64, start_us: u64, duration_us: u64) -> TraceEvent {
+        TraceEvent {
+            name: name.to_string(),
+            thread_id,
+            start: Duration::from_micros(start_us),
+            duration: Duration::from_micros(duration_us),
+        }
+    }
+
+    fn trace_events(recorder: &TraceRecorder) -> Vec<serde_json::Value> {
+        let trace: serde_json::Value = serde_json::from_str(&recorder.chrome_trace()).unwrap();
+        trace["traceEvents"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn escape_json_escapes_quotes_and_control_characters() {
+        assert_eq!(escape_json("plain"), "plain");
+        assert_eq!(escape_json(r#"say "hi""#), r#"say \"hi\""#);
+        assert_eq!(escape_json(r"C:\path"), r"C:\\path");
+        assert_eq!(escape_json("a\nb\tc\u{1}"), r"a\nb\u0009c\u0001");
+        assert_eq!(escape_json("grün"), "grün");
+    }
+
+    #[test]
+    fn chrome_trace_is_valid_json() {
+        let recorder = TraceRecorder::new();
+        recorder.start_capture();
+        recorder.record(event("render_pass", GPU_THREAD_ID, 1500, 250));
+        recorder.record(event("quoted \"pass\"\n\u{7}", 1, 1000, 2000));
+        recorder.stop_capture();
+
+        let events = trace_events(&recorder);
+        // the first event names the gpu track
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["ph"], "M");
+        assert_eq!(events[0]["args"]["name"], "gpu");
+
+        assert_eq!(events[1]["name"], "render_pass");
+        assert_eq!(events[1]["cat"], "gpu");
+        assert_eq!(events[1]["ts"].as_f64(), Some(1500.0));
+        assert_eq!(events[1]["dur"].as_f64(), Some(250.0));
+        assert_eq!(events[2]["name"], "quoted \"pass\"\n\u{7}");
+        assert_eq!(events[2]["cat"], "cpu");
+        assert_eq!(events[2]["tid"], 1);
+    }
+
+    #[test]
+    fn events_are_only_recorded_while_capturing() {
+        let recorder = TraceRecorder::new();
+        recorder.record(event("before", 1, 0, 1));
+        recorder.start_capture();
+        recorder.record(event("during", 1, 0, 1));
+        recorder.stop_capture();
+        recorder.record(event("after", 1, 0, 1));
+
+        let events = trace_events(&recorder);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1]["name"], "during");
+    }
+
+    #[test]
+    fn spans_are_named_after_their_label() {
+        let recorder = TraceRecorder::new();
+        let subscriber = tracing_subscriber::registry().with(TraceLayer::new(recorder.clone()));
+        recorder.start_capture();
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::trace_span!("pass", label = "bloom").entered();
+            let _span = tracing::trace_span!("update").entered();
+        });
+        recorder.stop_capture();
+
+        let mut names: Vec<_> = trace_events(&recorder)[1..]
+            .iter()
+            .map(|event| event["name"].as_str().unwrap().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["bloom", "update"]);
+    }
+}
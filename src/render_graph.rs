@@ -0,0 +1,733 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use anyhow::anyhow;
+
+use crate::{profiler::GpuProfiler, texture::Texture};
+
+/// texture owned by the graph, only alive between the first and last pass using it. textures of
+/// the same description whose lifetimes don't overlap share the same allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransientTexture {
+    label: &'static str,
+    format: wgpu::TextureFormat,
+    size_divisor: u32,
+    mip_level_count: u32,
+    sample_count: u32,
+}
+
+impl TransientTexture {
+    /// a single sampled texture the size of the surface without mip levels
+    pub fn new(label: &'static str, format: wgpu::TextureFormat) -> Self {
+        Self {
+            label,
+            format,
+            size_divisor: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+        }
+    }
+
+    /// the surface size is divided by this
+    pub fn size_divisor(mut self, size_divisor: u32) -> Self {
+        self.size_divisor = size_divisor.max(1);
+        self
+    }
+
+    pub fn mip_level_count(mut self, mip_level_count: u32) -> Self {
+        self.mip_level_count = mip_level_count.max(1);
+        self
+    }
+
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count.max(1);
+        self
+    }
+
+    pub fn size(&self, surface_width: u32, surface_height: u32) -> (u32, u32) {
+        (
+            (surface_width / self.size_divisor).max(1),
+            (surface_height / self.size_divisor).max(1),
+        )
+    }
+
+    /// everything but the label decides whether two textures can alias
+    fn compatible(&self, other: &TransientTexture) -> bool {
+        self.format == other.format
+            && self.size_divisor == other.size_divisor
+            && self.mip_level_count == other.mip_level_count
+            && self.sample_count == other.sample_count
+    }
+}
+
+/// transient textures kept alive across frames, so they only get allocated again after a resize
+#[derive(Default)]
+pub struct TexturePool {
+    width: u32,
+    height: u32,
+    textures: Vec<(TransientTexture, Texture)>,
+}
+
+impl TexturePool {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            textures: vec![],
+        }
+    }
+
+    /// drops all textures, they get recreated at the new size when they are needed next
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.textures.clear()
+    }
+
+    fn create(&mut self, device: &wgpu::Device, descriptor: TransientTexture) -> usize {
+        let (width, height) = descriptor.size(self.width, self.height);
+        let texture = Texture::create_render_target(
+            device,
+            width,
+            height,
+            descriptor.format,
+            descriptor.mip_level_count,
+            descriptor.sample_count,
+            descriptor.label,
+        );
+        self.textures.push((descriptor, texture));
+        self.textures.len() - 1
+    }
+}
+
+/// refers to a resource of the graph as it is after a specific pass wrote to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceHandle {
+    index: usize,
+    version: u32,
+}
+
+enum Resource<'a> {
+    View(&'a wgpu::TextureView),
+    Texture(&'a Texture),
+    Buffer(&'a wgpu::Buffer),
+    Transient(TransientTexture),
+    /// imported resource without a gpu object behind it, as tests have no device
+    #[cfg(test)]
+    Placeholder,
+}
+
+struct ResourceNode<'a> {
+    label: &'static str,
+    resource: Resource<'a>,
+    /// the pass which produced each version, starting at version 1
+    writers: Vec<usize>,
+}
+
+impl ResourceNode<'_> {
+    fn latest_version(&self) -> u32 {
+        self.writers.len() as u32
+    }
+
+    fn imported(&self) -> bool {
+        !matches!(self.resource, Resource::Transient(_))
+    }
+}
+
+type Execute<'a> = Box<dyn FnOnce(&mut PassContext) + 'a>;
+
+struct PassNode<'a> {
+    label: &'static str,
+    reads: Vec<ResourceHandle>,
+    writes: Vec<ResourceHandle>,
+    execute: Option<Execute<'a>>,
+}
+
+/// what a pass gets to record its commands
+pub struct PassContext<'r> {
+    pub device: &'r wgpu::Device,
+    pub encoder: &'r mut wgpu::CommandEncoder,
+    pub resources: &'r GraphResources<'r>,
+}
+
+enum ResolvedResource<'r> {
+    View(&'r wgpu::TextureView),
+    Texture(&'r Texture),
+    Buffer(&'r wgpu::Buffer),
+    /// transient textures not used by any scheduled pass aren't allocated
+    Unallocated,
+}
+
+/// the resources of the graph backed by actual textures and buffers while it executes
+pub struct GraphResources<'r> {
+    labels: Vec<&'static str>,
+    resources: Vec<ResolvedResource<'r>>,
+}
+
+impl<'r> GraphResources<'r> {
+    pub fn view(&self, handle: ResourceHandle) -> &'r wgpu::TextureView {
+        match self.resources[handle.index] {
+            ResolvedResource::View(view) => view,
+            ResolvedResource::Texture(texture) => &texture.view,
+            _ => panic!("{} is not a texture", self.labels[handle.index]),
+        }
+    }
+
+    /// panics for imported views, which don't carry the texture they belong to
+    pub fn texture(&self, handle: ResourceHandle) -> &'r Texture {
+        match self.resources[handle.index] {
+            ResolvedResource::Texture(texture) => texture,
+            _ => panic!("{} is not a texture", self.labels[handle.index]),
+        }
+    }
+
+    pub fn buffer(&self, handle: ResourceHandle) -> &'r wgpu::Buffer {
+        match self.resources[handle.index] {
+            ResolvedResource::Buffer(buffer) => buffer,
+            _ => panic!("{} is not a buffer", self.labels[handle.index]),
+        }
+    }
+}
+
+/// passes declare which resources they read and write, the graph orders them by those
+/// dependencies, culls passes whose results aren't used, allocates transient textures and records
+/// everything into a single command encoder
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<ResourceNode<'a>>,
+    passes: Vec<PassNode<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn import_view(
+        &mut self,
+        label: &'static str,
+        view: &'a wgpu::TextureView,
+    ) -> ResourceHandle {
+        self.add_resource(label, Resource::View(view))
+    }
+
+    pub fn import_texture(&mut self, label: &'static str, texture: &'a Texture) -> ResourceHandle {
+        self.add_resource(label, Resource::Texture(texture))
+    }
+
+    /// buffers are only tracked for ordering, passes writing them are never culled
+    pub fn import_buffer(
+        &mut self,
+        label: &'static str,
+        buffer: &'a wgpu::Buffer,
+    ) -> ResourceHandle {
+        self.add_resource(label, Resource::Buffer(buffer))
+    }
+
+    /// the contents are undefined until a pass writes to it
+    pub fn create_texture(&mut self, descriptor: TransientTexture) -> ResourceHandle {
+        self.add_resource(descriptor.label, Resource::Transient(descriptor))
+    }
+
+    pub fn add_pass(&mut self, label: &'static str) -> PassBuilder<'_, 'a> {
+        self.passes.push(PassNode {
+            label,
+            reads: vec![],
+            writes: vec![],
+            execute: None,
+        });
+        PassBuilder {
+            pass: self.passes.len() - 1,
+            graph: self,
+        }
+    }
+
+    /// every pass is measured as a cpu span and a gpu scope of the profiler
+    pub fn execute(
+        mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &mut TexturePool,
+        profiler: &mut GpuProfiler,
+    ) -> anyhow::Result<()> {
+        let order = self.schedule()?;
+
+        let descriptors: Vec<_> = pool
+            .textures
+            .iter()
+            .map(|(descriptor, _)| *descriptor)
+            .collect();
+        let (allocations, created) = self.allocate(&order, &descriptors);
+        for descriptor in created {
+            pool.create(device, descriptor);
+        }
+
+        let resources = GraphResources {
+            labels: self
+                .resources
+                .iter()
+                .map(|resource| resource.label)
+                .collect(),
+            resources: self
+                .resources
+                .iter()
+                .zip(&allocations)
+                .map(|(resource, allocation)| match resource.resource {
+                    Resource::View(view) => ResolvedResource::View(view),
+                    Resource::Texture(texture) => ResolvedResource::Texture(texture),
+                    Resource::Buffer(buffer) => ResolvedResource::Buffer(buffer),
+                    Resource::Transient(_) => match allocation {
+                        Some(slot) => ResolvedResource::Texture(&pool.textures[*slot].1),
+                        None => ResolvedResource::Unallocated,
+                    },
+                    #[cfg(test)]
+                    Resource::Placeholder => ResolvedResource::Unallocated,
+                })
+                .collect(),
+        };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("render_graph_encoder"),
+        });
+        profiler.begin_frame();
+        for pass in order {
+            let pass = &mut self.passes[pass];
+            let execute = pass.execute.take().ok_or_else(|| {
+                anyhow!("pass {} was never given anything to execute", pass.label)
+            })?;
+            let _span = tracing::trace_span!("pass", label = pass.label).entered();
+            let scope = profiler.begin_scope(&mut encoder, pass.label);
+            execute(&mut PassContext {
+                device,
+                encoder: &mut encoder,
+                resources: &resources,
+            });
+            profiler.end_scope(&mut encoder, scope);
+        }
+        profiler.end_frame(&mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+        profiler.frame_submitted(device);
+
+        Ok(())
+    }
+
+    /// assigns every transient texture used by the scheduled passes a slot of the pool, reusing
+    /// compatible slots no longer in use. `pool` describes the slots already allocated. returns
+    /// the slot of every resource and the descriptions of the slots which have to be appended to
+    /// the pool.
+    fn allocate(
+        &self,
+        order: &[usize],
+        pool: &[TransientTexture],
+    ) -> (Vec<Option<usize>>, Vec<TransientTexture>) {
+        // lifetime of every transient texture as positions in the schedule
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.len()];
+        for (position, &pass) in order.iter().enumerate() {
+            let pass = &self.passes[pass];
+            for handle in pass.reads.iter().chain(&pass.writes) {
+                let lifetime = &mut lifetimes[handle.index];
+                *lifetime = Some(match *lifetime {
+                    Some((first, _)) => (first, position),
+                    None => (position, position),
+                });
+            }
+        }
+
+        let mut slots = pool.to_vec();
+        let mut allocations: Vec<Option<usize>> = vec![None; self.resources.len()];
+        let mut in_use = vec![false; slots.len()];
+        for position in 0..order.len() {
+            for (index, resource) in self.resources.iter().enumerate() {
+                let Resource::Transient(descriptor) = resource.resource else {
+                    continue;
+                };
+                if !matches!(lifetimes[index], Some((first, _)) if first == position) {
+                    continue;
+                }
+                let free = slots
+                    .iter()
+                    .enumerate()
+                    .position(|(slot, other)| !in_use[slot] && other.compatible(&descriptor));
+                let slot = match free {
+                    Some(slot) => slot,
+                    None => {
+                        slots.push(descriptor);
+                        in_use.push(false);
+                        slots.len() - 1
+                    }
+                };
+                in_use[slot] = true;
+                allocations[index] = Some(slot);
+            }
+            for (index, lifetime) in lifetimes.iter().enumerate() {
+                if let (Some((_, last)), Some(slot)) = (lifetime, allocations[index]) {
+                    if *last == position {
+                        in_use[slot] = false
+                    }
+                }
+            }
+        }
+
+        (allocations, slots.split_off(pool.len()))
+    }
+
+    fn add_resource(&mut self, label: &'static str, resource: Resource<'a>) -> ResourceHandle {
+        self.resources.push(ResourceNode {
+            label,
+            resource,
+            writers: vec![],
+        });
+        ResourceHandle {
+            index: self.resources.len() - 1,
+            version: 0,
+        }
+    }
+
+    fn writer(&self, index: usize, version: u32) -> Option<usize> {
+        version
+            .checked_sub(1)
+            .map(|version| self.resources[index].writers[version as usize])
+    }
+
+    /// returns the passes to execute in order. passes are culled unless they write an imported
+    /// resource or something a remaining pass depends on. independent passes keep the order they
+    /// were added in.
+    fn schedule(&self) -> anyhow::Result<Vec<usize>> {
+        // passes whose results a pass consumes, and additionally passes which have to run before
+        // it because it overwrites what they read
+        let mut data_dependencies = vec![vec![]; self.passes.len()];
+        let mut dependencies = vec![vec![]; self.passes.len()];
+        for (pass, node) in self.passes.iter().enumerate() {
+            for read in &node.reads {
+                data_dependencies[pass].extend(self.writer(read.index, read.version));
+            }
+            for write in &node.writes {
+                data_dependencies[pass].extend(self.writer(write.index, write.version - 1));
+                for (reader, other) in self.passes.iter().enumerate() {
+                    let reads_previous = other
+                        .reads
+                        .iter()
+                        .any(|read| read.index == write.index && read.version == write.version - 1);
+                    if reader != pass && reads_previous {
+                        dependencies[pass].push(reader)
+                    }
+                }
+            }
+            dependencies[pass].extend(data_dependencies[pass].iter().copied());
+        }
+
+        let mut needed = vec![false; self.passes.len()];
+        let mut stack: Vec<usize> = self
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| {
+                node.writes.is_empty()
+                    || node
+                        .writes
+                        .iter()
+                        .any(|write| self.resources[write.index].imported())
+            })
+            .map(|(pass, _)| pass)
+            .collect();
+        while let Some(pass) = stack.pop() {
+            if !needed[pass] {
+                needed[pass] = true;
+                stack.extend(data_dependencies[pass].iter().copied());
+            }
+        }
+
+        let mut remaining = vec![0; self.passes.len()];
+        let mut dependents = vec![vec![]; self.passes.len()];
+        for pass in (0..self.passes.len()).filter(|pass| needed[*pass]) {
+            for &dependency in dependencies[pass]
+                .iter()
+                .filter(|dependency| needed[**dependency])
+            {
+                remaining[pass] += 1;
+                dependents[dependency].push(pass);
+            }
+        }
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..self.passes.len())
+            .filter(|pass| needed[*pass] && remaining[*pass] == 0)
+            .map(Reverse)
+            .collect();
+        let mut order = vec![];
+        while let Some(Reverse(pass)) = ready.pop() {
+            order.push(pass);
+            for &dependent in &dependents[pass] {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.push(Reverse(dependent))
+                }
+            }
+        }
+
+        if order.len() != needed.iter().filter(|needed| **needed).count() {
+            let cyclic: Vec<_> = (0..self.passes.len())
+                .filter(|pass| needed[*pass] && remaining[*pass] > 0)
+                .map(|pass| self.passes[pass].label)
+                .collect();
+            return Err(anyhow!("render graph has a cycle between {:?}", cyclic));
+        }
+        Ok(order)
+    }
+}
+
+/// declares the resources of a pass, finished by giving it the commands to record
+#[must_use = "a pass has to be given something to execute"]
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: usize,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    pub fn read(&mut self, handle: ResourceHandle) -> ResourceHandle {
+        self.graph.passes[self.pass].reads.push(handle);
+        handle
+    }
+
+    /// the returned handle refers to the contents after this pass, the given one has to refer
+    /// to the latest contents
+    pub fn write(&mut self, handle: ResourceHandle) -> ResourceHandle {
+        let resource = &mut self.graph.resources[handle.index];
+        assert_eq!(
+            handle.version,
+            resource.latest_version(),
+            "{} was already written to after this version",
+            resource.label
+        );
+        resource.writers.push(self.pass);
+        let handle = ResourceHandle {
+            index: handle.index,
+            version: resource.latest_version(),
+        };
+        self.graph.passes[self.pass].writes.push(handle);
+        handle
+    }
+
+    pub fn execute(self, execute: impl FnOnce(&mut PassContext) + 'a) {
+        self.graph.passes[self.pass].execute = Some(Box::new(execute));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    impl RenderGraph<'_> {
+        fn import_placeholder(&mut self, label: &'static str) -> ResourceHandle {
+            self.add_resource(label, Resource::Placeholder)
+        }
+    }
+
+    fn labels(graph: &RenderGraph) -> Vec<&'static str> {
+        graph
+            .schedule()
+            .unwrap()
+            .into_iter()
+            .map(|pass| graph.passes[pass].label)
+            .collect()
+    }
+
+    #[test]
+    fn passes_run_after_the_passes_they_read_from() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.import_placeholder("surface");
+        let scene = graph.create_texture(TransientTexture::new("scene", FORMAT));
+        let ui = graph.create_texture(TransientTexture::new("ui", FORMAT));
+        let blurred = graph.create_texture(TransientTexture::new("blurred", FORMAT));
+
+        let mut pass = graph.add_pass("scene");
+        let scene = pass.write(scene);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("ui");
+        let ui = pass.write(ui);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("blur");
+        pass.read(scene);
+        let blurred = pass.write(blurred);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("composite");
+        pass.read(ui);
+        pass.read(blurred);
+        pass.write(surface);
+        pass.execute(|_| ());
+
+        assert_eq!(labels(&graph), ["scene", "ui", "blur", "composite"]);
+    }
+
+    #[test]
+    fn readers_run_before_the_next_write() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.import_placeholder("surface");
+        let readback = graph.import_placeholder("readback");
+        let texture = graph.create_texture(TransientTexture::new("texture", FORMAT));
+
+        let mut pass = graph.add_pass("a");
+        let v1 = pass.write(texture);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("b");
+        let v2 = pass.write(v1);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("d");
+        pass.read(v2);
+        pass.write(surface);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("c");
+        pass.read(v1);
+        pass.write(readback);
+        pass.execute(|_| ());
+
+        // c reads what a wrote, so it has to run before b overwrites it, even though it was
+        // added last
+        assert_eq!(labels(&graph), ["a", "c", "b", "d"]);
+    }
+
+    #[test]
+    fn passes_nobody_reads_from_are_culled() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.import_placeholder("surface");
+        let used = graph.create_texture(TransientTexture::new("used", FORMAT));
+        let unused = graph.create_texture(TransientTexture::new("unused", FORMAT));
+        let dangling = graph.create_texture(TransientTexture::new("dangling", FORMAT));
+
+        let mut pass = graph.add_pass("producer");
+        let used = pass.write(used);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("unused_producer");
+        let unused = pass.write(unused);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("unused_consumer");
+        pass.read(unused);
+        let _ = pass.write(dangling);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("output");
+        pass.read(used);
+        pass.write(surface);
+        pass.execute(|_| ());
+
+        assert_eq!(labels(&graph), ["producer", "output"]);
+    }
+
+    #[test]
+    fn passes_writing_imported_resources_are_never_culled() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.import_placeholder("surface");
+        let depth = graph.import_placeholder("depth");
+        let texture = graph.create_texture(TransientTexture::new("texture", FORMAT));
+
+        let mut pass = graph.add_pass("prepass");
+        let texture = pass.write(texture);
+        pass.write(depth);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("unread");
+        pass.read(texture);
+        pass.write(surface);
+        pass.execute(|_| ());
+        // passes without writes are kept as well, as they can only have side effects
+        graph.add_pass("readback").execute(|_| ());
+
+        assert_eq!(labels(&graph), ["prepass", "unread", "readback"]);
+    }
+
+    #[test]
+    fn cycles_are_an_error() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.import_placeholder("surface");
+        let texture = graph.create_texture(TransientTexture::new("texture", FORMAT));
+
+        let mut pass = graph.add_pass("a");
+        let v1 = pass.write(texture);
+        pass.execute(|_| ());
+        let mut pass = graph.add_pass("b");
+        pass.read(v1);
+        let v2 = pass.write(v1);
+        pass.write(surface);
+        pass.execute(|_| ());
+        // a reads what b writes after it, and b what a writes
+        graph.passes[0].reads.push(v2);
+
+        assert!(graph.schedule().is_err());
+    }
+
+    /// builds a chain of passes each reading the texture of the previous one and writing its
+    /// own, the last one writing the surface
+    fn chain(graph: &mut RenderGraph, textures: &[TransientTexture]) -> Vec<ResourceHandle> {
+        let surface = graph.import_placeholder("surface");
+        let handles: Vec<_> = textures
+            .iter()
+            .map(|texture| graph.create_texture(*texture))
+            .collect();
+        let mut previous = None;
+        for &handle in &handles {
+            let mut pass = graph.add_pass("pass");
+            if let Some(previous) = previous {
+                pass.read(previous);
+            }
+            previous = Some(pass.write(handle));
+            pass.execute(|_| ());
+        }
+        let mut pass = graph.add_pass("output");
+        pass.read(previous.unwrap());
+        pass.write(surface);
+        pass.execute(|_| ());
+        handles
+    }
+
+    #[test]
+    fn textures_alias_once_their_lifetimes_ended() {
+        let mut graph = RenderGraph::new();
+        let texture = TransientTexture::new("texture", FORMAT);
+        let handles = chain(&mut graph, &[texture, texture, texture]);
+        let order = graph.schedule().unwrap();
+        let (allocations, created) = graph.allocate(&order, &[]);
+
+        // the second pass reads the first texture while writing the second, so they can't share
+        // a slot, the third reuses the first
+        assert_eq!(created.len(), 2);
+        let slot = |handle: ResourceHandle| allocations[handle.index].unwrap();
+        assert_ne!(slot(handles[0]), slot(handles[1]));
+        assert_ne!(slot(handles[1]), slot(handles[2]));
+        assert_eq!(slot(handles[0]), slot(handles[2]));
+    }
+
+    #[test]
+    fn textures_only_alias_compatible_textures() {
+        let mut graph = RenderGraph::new();
+        let handles = chain(
+            &mut graph,
+            &[
+                TransientTexture::new("full", FORMAT),
+                TransientTexture::new("half", FORMAT).size_divisor(2),
+                TransientTexture::new("other_format", wgpu::TextureFormat::R8Unorm),
+            ],
+        );
+        let order = graph.schedule().unwrap();
+        let (allocations, created) = graph.allocate(&order, &[]);
+
+        assert_eq!(created.len(), 3);
+        let mut slots: Vec<_> = handles
+            .iter()
+            .map(|handle| allocations[handle.index].unwrap())
+            .collect();
+        slots.dedup();
+        assert_eq!(slots.len(), 3);
+    }
+
+    #[test]
+    fn textures_reuse_the_slots_of_the_pool() {
+        let mut graph = RenderGraph::new();
+        let texture = TransientTexture::new("texture", FORMAT);
+        let handles = chain(&mut graph, &[texture, texture]);
+        let order = graph.schedule().unwrap();
+        let (allocations, created) = graph.allocate(&order, &[texture]);
+
+        assert_eq!(created, [texture]);
+        assert_eq!(allocations[handles[0].index], Some(0));
+        assert_eq!(allocations[handles[1].index], Some(1));
+        // the surface isn't transient
+        assert_eq!(allocations[0], None);
+    }
+}
@@ -2,13 +2,34 @@ use anyhow::anyhow;
 use wgpu::util::DeviceExt;
 
 use crate::{
-    camera::{Camera, CameraController, CameraUniform},
+    camera::{
+        Camera, CameraController, CameraMode, CameraUniform, OrbitCameraController, Projection,
+        WalkCameraController,
+    },
+    camera_path::{CameraPath, CameraPathPlayer, CameraPathRecorder},
+    culling::{Aabb, Frustum},
+    fog::{Fog, FogUniform},
+    hdr::{Hdr, HDR_FORMAT},
     input_manager::InputManager,
+    light::{Light, LightUniform},
     model::{Mesh, Vertex},
-    texture::Texture,
+    picking::{Hit, ObjectId, Pickable, Ray},
+    post_process::{PostProcess, POST_FORMAT},
+    profiler::GpuProfiler,
+    render_graph::{RenderGraph, ResourceHandle, TexturePool, TransientTexture},
+    sky::{DayNightCycle, Sky},
+    skybox::Skybox,
+    ssao::Ssao,
+    terrain::HeightField,
+    texture::{AtlasRect, Texture, TextureAtlasBuilder, TextureOptions},
     timing::Timing,
 };
 
+const SHADER_SOURCE: &str = concat!(
+    include_str!("./shaders/common.wgsl"),
+    include_str!("./shaders/shader.wgsl")
+);
+
 const CLEAR_COLOR: wgpu::Color = wgpu::Color {
     r: 0.85,
     g: 0.8,
@@ -16,25 +37,131 @@ const CLEAR_COLOR: wgpu::Color = wgpu::Color {
     a: 1.0,
 };
 
+/// how far in front of the camera the orbit target is placed when switching to orbit mode
+const ORBIT_FOCUS_DISTANCE: f32 = 5.0;
+
+/// how often a keyframe is taken while recording a camera path
+const CAMERA_PATH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// the ground walk mode stands on
+const TERRAIN_SIZE: f32 = 200.0;
+const TERRAIN_HEIGHT: f32 = -1.7;
+
+/// corners of the circle mesh
+const MESH_RESOLUTION: usize = 8;
+
+/// ids `pick` reports hits with
+pub const TERRAIN_OBJECT: ObjectId = ObjectId(0);
+pub const MESH_OBJECT: ObjectId = ObjectId(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msaa {
+    Off,
+    X2,
+    X4,
+}
+
+impl Msaa {
+    pub fn sample_count(&self) -> u32 {
+        match self {
+            Msaa::Off => 1,
+            Msaa::X2 => 2,
+            Msaa::X4 => 4,
+        }
+    }
+
+    /// cycles downwards so an unsupported sample count falling back to a lower one can't get
+    /// stuck
+    pub fn next(&self) -> Self {
+        match self {
+            Msaa::X4 => Msaa::X2,
+            Msaa::X2 => Msaa::Off,
+            Msaa::Off => Msaa::X4,
+        }
+    }
+
+    fn lower(&self) -> Self {
+        match self {
+            Msaa::Off | Msaa::X2 => Msaa::Off,
+            Msaa::X4 => Msaa::X2,
+        }
+    }
+}
+
+/// format, sample count and depth convention every pipeline drawing into the main pass has to
+/// match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderTarget {
+    pub color_format: wgpu::TextureFormat,
+    pub msaa: Msaa,
+    /// see `Camera::reversed_z`
+    pub reversed_z: bool,
+}
+
+impl RenderTarget {
+    /// depth test letting fragments closer to the camera through
+    pub fn depth_compare(&self, or_equal: bool) -> wgpu::CompareFunction {
+        match (self.reversed_z, or_equal) {
+            (false, false) => wgpu::CompareFunction::Less,
+            (false, true) => wgpu::CompareFunction::LessEqual,
+            (true, false) => wgpu::CompareFunction::Greater,
+            (true, true) => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkyMode {
+    /// static cube texture, lit by a fixed light
+    Skybox,
+    /// analytic sky following the day/night cycle
+    Procedural,
+}
+
 pub struct Renderer {
-    instance: wgpu::Instance,
     surface: wgpu::Surface,
     surface_config: wgpu::SurfaceConfiguration,
     adapter: wgpu::Adapter,
     device: wgpu::Device,
     queue: wgpu::Queue,
 
-    vertex_buffers: Vec<wgpu::Buffer>,
-    index_buffers: Vec<wgpu::Buffer>,
-    render_pipelines: Vec<wgpu::RenderPipeline>,
-
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
+    depth_prepass_pipeline_layout: wgpu::PipelineLayout,
+    depth_prepass_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
+    /// cpu copy of what the vertex and index buffers hold, for picking
+    mesh: Mesh,
+    mesh_bounds: Option<Aabb>,
+    /// whether the mesh is in view of the camera this frame, it isn't drawn otherwise
+    mesh_visible: bool,
+    /// rects of the images in the diffuse texture atlas
+    diffuse_rects: Vec<AtlasRect>,
+    /// which of `diffuse_rects` the mesh is textured with
+    diffuse_image: usize,
     diffuse_bind_group: wgpu::BindGroup,
     depth_texture: Texture,
+    msaa: Msaa,
+    texture_pool: TexturePool,
+    profiler: GpuProfiler,
+    hdr: Hdr,
+    post_process: PostProcess,
+    ssao: Ssao,
     camera: RendererCamera,
     camera_controller: CameraController,
+    orbit_controller: OrbitCameraController,
+    walk_controller: WalkCameraController,
+    camera_mode: CameraMode,
+    camera_path_recorder: Option<CameraPathRecorder>,
+    camera_path_player: Option<CameraPathPlayer>,
+    terrain: HeightField,
+    environment: RendererEnvironment,
+    sky_mode: SkyMode,
+    skybox: Skybox,
+    sky: Sky,
+    day_night_cycle: DayNightCycle,
+    previous_day_night_cycle: DayNightCycle,
 }
 
 impl Renderer {
@@ -52,7 +179,9 @@ impl Renderer {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("device"),
-                    features: wgpu::Features::empty(),
+                    features: adapter.features()
+                        & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                            | wgpu::Features::TIMESTAMP_QUERY),
                     limits: wgpu::Limits::default(),
                 },
                 None,
@@ -64,8 +193,7 @@ impl Renderer {
             .formats
             .iter()
             .copied()
-            .filter(|f| f.describe().srgb)
-            .next()
+            .find(|f| f.describe().srgb)
             .unwrap_or(surface_capabilities.formats[0]);
         let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -78,12 +206,17 @@ impl Renderer {
         };
         surface.configure(&device, &surface_config);
 
-        let diffuse_texture = crate::texture::Texture::from_bytes(
+        let mut diffuse_atlas = TextureAtlasBuilder::new();
+        diffuse_atlas.add_image(&image::load_from_memory(include_bytes!("../res/cube.png"))?);
+        diffuse_atlas.add_image(&image::load_from_memory(include_bytes!("../res/tree.png"))?);
+        let diffuse_atlas = diffuse_atlas.build(
             &device,
             &queue,
-            wgpu::FilterMode::Linear,
-            include_bytes!("../res/cube.png"),
+            &TextureOptions::new()
+                .label("diffuse_texture")
+                .anisotropy(16),
         )?;
+        let diffuse_texture = diffuse_atlas.texture;
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("texture_bind_group"),
@@ -121,7 +254,20 @@ impl Renderer {
             ],
         });
 
-        let depth_texture = Texture::create_depth_texture(&device, &surface_config);
+        let msaa = Self::supported_msaa(&adapter, &device, HDR_FORMAT, Msaa::X4);
+        let depth_texture =
+            Texture::create_depth_texture(&device, &surface_config, msaa.sample_count());
+        let texture_pool = TexturePool::new(surface_config.width, surface_config.height);
+        let profiler = GpuProfiler::new(&device, &queue);
+        let hdr = Hdr::new(
+            &device,
+            POST_FORMAT,
+            adapter
+                .get_downlevel_capabilities()
+                .flags
+                .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS),
+        );
+        let post_process = PostProcess::new(&device, &queue, &surface_config);
 
         let camera = RendererCamera::new(
             &device,
@@ -133,50 +279,126 @@ impl Renderer {
                 aspect: target.inner_size().width as f32 / target.inner_size().height as f32,
                 near: 0.1,
                 far: 100.0,
+                projection: Projection::Perspective,
+                infinite_far: false,
+                reversed_z: false,
             },
         );
         let camera_controller = CameraController::new(10.0);
+        let target = RenderTarget {
+            color_format: HDR_FORMAT,
+            msaa,
+            reversed_z: camera.camera.reversed_z,
+        };
+        let environment_bind_group_layout = RendererEnvironment::create_bind_group_layout(&device);
+        let skybox = Skybox::from_gradient(
+            &device,
+            &queue,
+            &camera.bind_group_layout,
+            &environment_bind_group_layout,
+            target,
+            [90, 140, 220],
+            [217, 204, 255],
+        )?;
+        let sky = Sky::new(
+            &device,
+            &camera.bind_group_layout,
+            &environment_bind_group_layout,
+            target,
+        );
+        let day_night_cycle = DayNightCycle::new(std::time::Duration::from_secs(120));
+        let environment = RendererEnvironment::new(
+            &device,
+            environment_bind_group_layout,
+            day_night_cycle.light(),
+            Fog::default(),
+            &sky,
+            &skybox,
+        );
+
+        let ssao = Ssao::new(
+            &device,
+            &queue,
+            &surface_config,
+            &camera.bind_group_layout,
+            &depth_texture,
+            msaa,
+        );
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("render_pipeline_layout"),
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera.bind_group_layout,
+                    &environment.bind_group_layout,
+                    ssao.lighting_bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline =
+            Self::create_render_pipeline(&device, &render_pipeline_layout, SHADER_SOURCE, target);
+        let depth_prepass_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("depth_prepass_pipeline_layout"),
                 bind_group_layouts: &[&texture_bind_group_layout, &camera.bind_group_layout],
                 push_constant_ranges: &[],
             });
-        let render_pipeline = Self::create_render_pipeline(
+        let depth_prepass_pipeline = Self::create_depth_prepass_pipeline(
             &device,
-            &render_pipeline_layout,
-            include_str!("./shaders/shader.wgsl"),
+            &depth_prepass_pipeline_layout,
+            SHADER_SOURCE,
+            target,
         );
-        let mesh = Mesh::create_circle(8)?;
+        let diffuse_image = 0;
+        let mesh = Mesh::create_circle(MESH_RESOLUTION)?
+            .with_atlas_rect(&diffuse_atlas.rects[diffuse_image]);
         let vertex_buffer = Self::create_vertex_buffer(&device, &mesh.vertices);
         let index_buffer = Self::create_index_buffer(&device, &mesh.indices);
+        let mesh_bounds = Aabb::from_mesh(&mesh);
         // let vertex_buffer = Self::create_vertex_buffer(&device, crate::model::VERTICES);
         // let index_buffer = Self::create_index_buffer(&device, &[0, 1, 2, 0, 2, 3, 0, 3, 4]);
 
-        let vertex_buffers = vec![];
-        let index_buffers = vec![];
-        let render_pipelines = vec![];
-
         Ok(Self {
-            instance,
             surface,
             adapter,
             device,
             queue,
 
-            vertex_buffers,
-            index_buffers,
-            render_pipelines,
-
             surface_config,
+            render_pipeline_layout,
             render_pipeline,
+            depth_prepass_pipeline_layout,
+            depth_prepass_pipeline,
             vertex_buffer,
             index_buffer,
+            mesh_bounds,
+            mesh,
+            mesh_visible: true,
+            diffuse_rects: diffuse_atlas.rects,
+            diffuse_image,
             diffuse_bind_group,
             depth_texture,
+            msaa,
+            texture_pool,
+            profiler,
+            hdr,
+            post_process,
+            ssao,
             camera,
             camera_controller,
+            orbit_controller: OrbitCameraController::new(cgmath::Point3::new(0.0, 0.0, 0.0), 3.0),
+            walk_controller: WalkCameraController::new(4.0),
+            camera_mode: CameraMode::Fly,
+            camera_path_recorder: None,
+            camera_path_player: None,
+            terrain: HeightField::flat(TERRAIN_SIZE, TERRAIN_HEIGHT),
+            environment,
+            sky_mode: SkyMode::Procedural,
+            skybox,
+            sky,
+            previous_day_night_cycle: day_night_cycle.clone(),
+            day_night_cycle,
         })
     }
 
@@ -185,136 +407,615 @@ impl Renderer {
             self.surface_config.width = width;
             self.surface_config.height = height;
             self.surface.configure(&self.device, &self.surface_config);
-            self.depth_texture = Texture::create_depth_texture(&self.device, &self.surface_config);
+            self.depth_texture = Texture::create_depth_texture(
+                &self.device,
+                &self.surface_config,
+                self.msaa.sample_count(),
+            );
+            self.texture_pool.resize(width, height);
+            self.ssao
+                .resize(&self.device, &self.surface_config, &self.depth_texture);
             self.camera
                 .set_aspect_ratio(&self.queue, width as f32 / height as f32);
             Ok(())
         } else {
-            return Err(anyhow!("size has to be greater than zero"));
+            Err(anyhow!("size has to be greater than zero"))
+        }
+    }
+
+    pub fn msaa(&self) -> Msaa {
+        self.msaa
+    }
+
+    /// falls back to the highest supported sample count below the requested one
+    pub fn set_msaa(&mut self, msaa: Msaa) {
+        let msaa = Self::supported_msaa(&self.adapter, &self.device, HDR_FORMAT, msaa);
+        if msaa == self.msaa {
+            return;
+        }
+        self.msaa = msaa;
+        self.depth_texture =
+            Texture::create_depth_texture(&self.device, &self.surface_config, msaa.sample_count());
+        self.set_render_target();
+        self.ssao.set_msaa(
+            &self.device,
+            &self.camera.bind_group_layout,
+            &self.depth_texture,
+            msaa,
+        );
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.camera.camera.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.camera.camera.projection = projection;
+        self.camera.previous.projection = projection
+    }
+
+    pub fn infinite_far(&self) -> bool {
+        self.camera.camera.infinite_far
+    }
+
+    pub fn set_infinite_far(&mut self, infinite_far: bool) {
+        self.camera.camera.infinite_far = infinite_far;
+        self.camera.previous.infinite_far = infinite_far
+    }
+
+    pub fn reversed_z(&self) -> bool {
+        self.camera.camera.reversed_z
+    }
+
+    /// recreates every pipeline testing depth
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        if reversed_z == self.reversed_z() {
+            return;
+        }
+        self.camera.camera.reversed_z = reversed_z;
+        self.camera.previous.reversed_z = reversed_z;
+        self.set_render_target()
+    }
+
+    pub fn render_target(&self) -> RenderTarget {
+        RenderTarget {
+            color_format: HDR_FORMAT,
+            msaa: self.msaa,
+            reversed_z: self.camera.camera.reversed_z,
+        }
+    }
+
+    /// recreates the pipelines drawing into the main pass after the render target changed
+    fn set_render_target(&mut self) {
+        let target = self.render_target();
+        self.render_pipeline = Self::create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            SHADER_SOURCE,
+            target,
+        );
+        self.depth_prepass_pipeline = Self::create_depth_prepass_pipeline(
+            &self.device,
+            &self.depth_prepass_pipeline_layout,
+            SHADER_SOURCE,
+            target,
+        );
+        self.skybox.set_render_target(&self.device, target);
+        self.sky.set_render_target(&self.device, target);
+    }
+
+    pub fn sky_mode(&self) -> SkyMode {
+        self.sky_mode
+    }
+
+    pub fn set_sky_mode(&mut self, sky_mode: SkyMode) {
+        self.sky_mode = sky_mode
+    }
+
+    pub fn diffuse_image(&self) -> usize {
+        self.diffuse_image
+    }
+
+    pub fn diffuse_image_count(&self) -> usize {
+        self.diffuse_rects.len()
+    }
+
+    /// textures the mesh with another image of the diffuse atlas
+    pub fn set_diffuse_image(&mut self, index: usize) -> anyhow::Result<()> {
+        let rect = self
+            .diffuse_rects
+            .get(index)
+            .ok_or_else(|| anyhow!("there is no diffuse image {}", index))?;
+        self.mesh = Mesh::create_circle(MESH_RESOLUTION)?.with_atlas_rect(rect);
+        self.queue.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(self.mesh.vertices.as_slice()),
+        );
+        self.diffuse_image = index;
+        Ok(())
+    }
+
+    pub fn fog(&self) -> &Fog {
+        &self.environment.fog
+    }
+
+    pub fn fog_mut(&mut self) -> &mut Fog {
+        &mut self.environment.fog
+    }
+
+    pub fn hdr(&self) -> &Hdr {
+        &self.hdr
+    }
+
+    pub fn hdr_mut(&mut self) -> &mut Hdr {
+        &mut self.hdr
+    }
+
+    pub fn post_process(&self) -> &PostProcess {
+        &self.post_process
+    }
+
+    pub fn post_process_mut(&mut self) -> &mut PostProcess {
+        &mut self.post_process
+    }
+
+    pub fn ssao(&self) -> &Ssao {
+        &self.ssao
+    }
+
+    pub fn ssao_mut(&mut self) -> &mut Ssao {
+        &mut self.ssao
+    }
+
+    pub fn profiler(&self) -> &GpuProfiler {
+        &self.profiler
+    }
+
+    pub fn profiler_mut(&mut self) -> &mut GpuProfiler {
+        &mut self.profiler
+    }
+
+    pub fn camera_mode(&self) -> CameraMode {
+        self.camera_mode
+    }
+
+    /// switching to orbit starts orbiting the point in front of the camera
+    pub fn set_camera_mode(&mut self, camera_mode: CameraMode) {
+        if camera_mode == CameraMode::Orbit && self.camera_mode != CameraMode::Orbit {
+            self.orbit_controller
+                .focus(&self.camera.camera, ORBIT_FOCUS_DISTANCE)
+        }
+        self.camera_mode = camera_mode
+    }
+
+    /// whether the active camera controller is being dragged or looked around with
+    pub fn camera_grabs_cursor(&self) -> bool {
+        match self.camera_mode {
+            CameraMode::Fly | CameraMode::Walk => self.camera_controller.looking(),
+            CameraMode::Orbit => self.orbit_controller.dragging(),
+        }
+    }
+
+    pub fn camera_controller(&self) -> &CameraController {
+        &self.camera_controller
+    }
+
+    pub fn camera_controller_mut(&mut self) -> &mut CameraController {
+        &mut self.camera_controller
+    }
+
+    pub fn recording_camera_path(&self) -> bool {
+        self.camera_path_recorder.is_some()
+    }
+
+    pub fn start_recording_camera_path(&mut self) {
+        self.camera_path_recorder = Some(CameraPathRecorder::new(CAMERA_PATH_INTERVAL))
+    }
+
+    /// returns the recorded path, if recording
+    pub fn stop_recording_camera_path(&mut self) -> Option<CameraPath> {
+        self.camera_path_recorder
+            .take()
+            .map(|recorder| recorder.finish(&self.camera.camera))
+    }
+
+    pub fn playing_camera_path(&self) -> bool {
+        self.camera_path_player.is_some()
+    }
+
+    /// moves the camera along `path` instead of the camera controllers until it ends or is
+    /// stopped
+    pub fn play_camera_path(&mut self, path: CameraPath, looping: bool) {
+        self.camera_path_player = Some(CameraPathPlayer::new(path, looping))
+    }
+
+    pub fn stop_camera_path(&mut self) {
+        self.camera_path_player = None
+    }
+
+    /// the closest object under a pixel, e.g. `InputManager::mouse_position`, as seen in the
+    /// last rendered frame
+    pub fn pick(&self, position: (f32, f32)) -> Option<Hit> {
+        let camera = &self.camera.rendered;
+        let ray = Ray::from_screen(
+            camera,
+            position,
+            (self.surface_config.width, self.surface_config.height),
+        )?;
+        crate::picking::pick(
+            &ray,
+            [
+                (
+                    TERRAIN_OBJECT,
+                    Pickable::HeightField {
+                        field: &self.terrain,
+                        max_distance: camera.far,
+                    },
+                ),
+                (MESH_OBJECT, Pickable::Mesh(&self.mesh)),
+            ],
+        )
+    }
+
+    pub fn walk_controller(&self) -> &WalkCameraController {
+        &self.walk_controller
+    }
+
+    pub fn walk_controller_mut(&mut self) -> &mut WalkCameraController {
+        &mut self.walk_controller
+    }
+
+    pub fn terrain(&self) -> &HeightField {
+        &self.terrain
+    }
+
+    pub fn set_terrain(&mut self, terrain: HeightField) {
+        self.terrain = terrain
+    }
+
+    pub fn orbit_controller(&self) -> &OrbitCameraController {
+        &self.orbit_controller
+    }
+
+    pub fn orbit_controller_mut(&mut self) -> &mut OrbitCameraController {
+        &mut self.orbit_controller
+    }
+
+    pub fn set_color_grading_lut(&mut self, image: &image::DynamicImage) -> anyhow::Result<()> {
+        self.post_process.set_lut(&self.device, &self.queue, image)
+    }
+
+    /// advances the simulation by one step of `Timing::fixed_time_delta`
+    pub fn fixed_update(&mut self, input: &InputManager, timing: &Timing) {
+        let time_delta = timing.fixed_time_delta();
+        self.camera.previous = self.camera.camera.clone();
+        if let Some(player) = &mut self.camera_path_player {
+            player.update(&mut self.camera.camera, time_delta);
+            if player.finished() {
+                self.camera_path_player = None
+            }
+        } else {
+            self.update_camera_controller(input, time_delta)
         }
+        if let Some(recorder) = &mut self.camera_path_recorder {
+            recorder.update(&self.camera.camera, time_delta)
+        }
+        self.previous_day_night_cycle = self.day_night_cycle.clone();
+        self.day_night_cycle.update(input, time_delta);
     }
 
+    fn update_camera_controller(&mut self, input: &InputManager, time_delta: std::time::Duration) {
+        match self.camera_mode {
+            CameraMode::Fly => {
+                self.camera_controller
+                    .update_camera(&mut self.camera.camera, input, time_delta)
+            }
+            CameraMode::Orbit => self
+                .orbit_controller
+                .update_camera(&mut self.camera.camera, time_delta),
+            CameraMode::Walk => self.walk_controller.update_camera(
+                &mut self.camera.camera,
+                input,
+                time_delta,
+                &self.terrain,
+            ),
+        }
+    }
+
+    /// runs once per frame after the simulation steps of the frame. renders the simulation
+    /// interpolated between the last two steps.
     pub fn update(&mut self, input: &InputManager, timing: &Timing) {
-        self.camera_controller
-            .update_camera(&mut self.camera.camera, input, timing);
-        self.camera.uniform.update(&self.camera.camera);
+        let alpha = timing.interpolation_alpha();
+        self.day_night_cycle.handle_input(input);
+        match self.camera_mode {
+            _ if self.camera_path_player.is_some() => (),
+            CameraMode::Fly | CameraMode::Walk => {
+                self.camera_controller
+                    .handle_input(&mut self.camera.camera, input);
+                // looking around follows the mouse every frame instead of the simulation steps,
+                // so the direction isn't interpolated
+                self.camera.previous.direction = self.camera.camera.direction;
+            }
+            CameraMode::Orbit => self.orbit_controller.handle_input(input),
+        }
+
+        let camera = self.camera.previous.interpolate(&self.camera.camera, alpha);
+        let frustum = Frustum::from_matrix(camera.build_view_projection_matrix());
+        self.camera.uniform.update(&camera);
+        self.camera.rendered = camera;
         self.queue.write_buffer(
             &self.camera.buffer,
             0,
             bytemuck::cast_slice(&[self.camera.uniform]),
         );
-        if input.mouse_delta() != (0.0, 0.0) {
-            let resolution = self.vertex_buffer.size() as usize / std::mem::size_of::<Vertex>()
-                - input.mouse_delta().1 as usize;
-            let mesh = Mesh::create_circle(resolution).unwrap();
-            self.queue.write_buffer(
-                &self.vertex_buffer,
-                0,
-                bytemuck::cast_slice(mesh.vertices.as_slice()),
-            );
-            self.queue.write_buffer(
-                &self.index_buffer,
-                0,
-                bytemuck::cast_slice(mesh.indices.as_slice()),
-            );
-        }
+        let day_night_cycle = self
+            .previous_day_night_cycle
+            .interpolate(&self.day_night_cycle, alpha);
+        self.sky.update(&self.queue, &day_night_cycle);
+        let light = match self.sky_mode {
+            SkyMode::Skybox => Light::default(),
+            SkyMode::Procedural => day_night_cycle.light(),
+        };
+        self.environment.light = light;
+        // fading isn't part of the simulation, it goes on while paused
+        self.environment.fog.update(timing.real_time_delta());
+        self.environment.update(
+            &self.queue,
+            self.camera.camera.far,
+            self.sky_mode == SkyMode::Procedural,
+        );
+        self.ssao.update(&self.queue);
+        self.hdr.update(&self.queue, timing.time_delta());
+        self.post_process
+            .update(&self.queue, timing.time_since_start());
+        self.mesh_visible = self
+            .mesh_bounds
+            .is_some_and(|bounds| frustum.intersects_aabb(&bounds));
     }
 
     pub fn render(&mut self) -> anyhow::Result<()> {
+        self.execute_graph(Self::build_frame)
+    }
+
+    pub fn clear(&mut self, color: wgpu::Color) -> anyhow::Result<()> {
+        self.execute_graph(|_, graph, surface| {
+            let mut pass = graph.add_pass("clear_pass");
+            let surface = pass.write(surface);
+            pass.execute(move |context| {
+                context
+                    .encoder
+                    .begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("clear_pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: context.resources.view(surface),
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(color),
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+            });
+        })
+    }
+
+    /// builds a render graph writing into the surface texture, executes it and presents the
+    /// surface
+    fn execute_graph(
+        &mut self,
+        build: impl for<'a> FnOnce(&'a Self, &mut RenderGraph<'a>, ResourceHandle),
+    ) -> anyhow::Result<()> {
         let output = self.surface.get_current_texture()?;
         let texture_view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut command_encoder =
-            self.device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("command_encoder"),
-                });
 
-        {
-            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("render_pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view,
+        let mut texture_pool = std::mem::take(&mut self.texture_pool);
+        let mut profiler = std::mem::take(&mut self.profiler);
+        let mut graph = RenderGraph::new();
+        let surface = graph.import_view("surface", &texture_view);
+        build(self, &mut graph, surface);
+        let result = graph.execute(&self.device, &self.queue, &mut texture_pool, &mut profiler);
+        self.texture_pool = texture_pool;
+        self.profiler = profiler;
+        result?;
+
+        output.present();
+
+        Ok(())
+    }
+
+    fn build_frame<'a>(&'a self, graph: &mut RenderGraph<'a>, surface: ResourceHandle) {
+        let mut depth = graph.import_texture("depth", &self.depth_texture);
+
+        // ssao needs the depth of the scene before lighting it
+        let occlusion = self.ssao.enabled.then(|| {
+            let mut pass = graph.add_pass("depth_prepass");
+            depth = pass.write(depth);
+            pass.execute(move |context| {
+                let mut render_pass =
+                    context
+                        .encoder
+                        .begin_render_pass(&wgpu::RenderPassDescriptor {
+                            label: Some("depth_prepass"),
+                            color_attachments: &[],
+                            depth_stencil_attachment: Some(
+                                wgpu::RenderPassDepthStencilAttachment {
+                                    view: &self.depth_texture.view,
+                                    depth_ops: Some(wgpu::Operations {
+                                        load: wgpu::LoadOp::Clear(self.camera.camera.far_depth()),
+                                        store: true,
+                                    }),
+                                    stencil_ops: None,
+                                },
+                            ),
+                        });
+                render_pass.set_pipeline(&self.depth_prepass_pipeline);
+                render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.camera.bind_group, &[]);
+                self.draw_mesh(&mut render_pass);
+            });
+            self.ssao.add_passes(graph, depth, &self.camera.bind_group)
+        });
+
+        let hdr = graph.create_texture(TransientTexture::new("hdr", HDR_FORMAT));
+        let msaa_framebuffer = (self.msaa != Msaa::Off).then(|| {
+            graph.create_texture(
+                TransientTexture::new("msaa_framebuffer", HDR_FORMAT)
+                    .sample_count(self.msaa.sample_count()),
+            )
+        });
+        let mut pass = graph.add_pass("render_pass");
+        if let Some(occlusion) = occlusion {
+            pass.read(occlusion);
+        }
+        let depth_loaded = occlusion.is_some();
+        pass.write(depth);
+        let hdr = pass.write(hdr);
+        let msaa_framebuffer = msaa_framebuffer.map(|framebuffer| pass.write(framebuffer));
+        pass.execute(move |context| {
+            let occlusion_bind_group = occlusion.map(|occlusion| {
+                self.ssao.create_lighting_bind_group(
+                    context.device,
+                    context.resources.texture(occlusion),
+                )
+            });
+            // renders into the multisampled framebuffer and resolves into the hdr target if msaa
+            // is enabled
+            let color_attachment = match msaa_framebuffer {
+                Some(framebuffer) => wgpu::RenderPassColorAttachment {
+                    view: context.resources.view(framebuffer),
+                    resolve_target: Some(context.resources.view(hdr)),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(CLEAR_COLOR),
+                        store: false,
+                    },
+                },
+                None => wgpu::RenderPassColorAttachment {
+                    view: context.resources.view(hdr),
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(CLEAR_COLOR),
                         store: true,
                     },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
+                },
+            };
+            let mut render_pass = context
+                .encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("render_pass"),
+                    color_attachments: &[Some(color_attachment)],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: if depth_loaded {
+                                wgpu::LoadOp::Load
+                            } else {
+                                wgpu::LoadOp::Clear(self.camera.camera.far_depth())
+                            },
+                            store: true,
+                        }),
+                        stencil_ops: None,
                     }),
-                    stencil_ops: None,
-                }),
-            });
+                });
 
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
             render_pass.set_bind_group(1, &self.camera.bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(
-                0..self.index_buffer.size() as u32 / std::mem::size_of::<u32>() as u32,
-                0,
-                0..1,
-            )
-        }
+            render_pass.set_bind_group(2, &self.environment.bind_group, &[]);
+            render_pass.set_bind_group(
+                3,
+                occlusion_bind_group
+                    .as_ref()
+                    .unwrap_or(self.ssao.fallback_lighting_bind_group()),
+                &[],
+            );
+            self.draw_mesh(&mut render_pass);
 
-        self.queue.submit(std::iter::once(command_encoder.finish()));
-        output.present();
+            match self.sky_mode {
+                SkyMode::Skybox => self.skybox.render(
+                    &mut render_pass,
+                    &self.camera.bind_group,
+                    &self.environment.bind_group,
+                ),
+                SkyMode::Procedural => self.sky.render(
+                    &mut render_pass,
+                    &self.camera.bind_group,
+                    &self.environment.bind_group,
+                ),
+            }
+        });
 
-        Ok(())
+        let size = (self.surface_config.width, self.surface_config.height);
+        let hdr = self.post_process.add_before_tonemapping(graph, hdr, size);
+        let ldr = graph.create_texture(TransientTexture::new("ldr", POST_FORMAT));
+        let ldr = self.hdr.add_passes(graph, hdr, ldr);
+        self.post_process.add_after_tonemapping(graph, ldr, surface);
     }
 
-    pub fn clear(&mut self, color: wgpu::Color) -> anyhow::Result<()> {
-        let output = self.surface.get_current_texture()?;
-        let texture_view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut command_encoder =
-            self.device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("command_encoder"),
-                });
-
-        {
-            command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("render_pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(color),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
+    fn draw_mesh<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if !self.mesh_visible {
+            return;
         }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(
+            0..self.index_buffer.size() as u32 / std::mem::size_of::<u32>() as u32,
+            0,
+            0..1,
+        );
+    }
 
-        self.queue.submit(std::iter::once(command_encoder.finish()));
-        output.present();
+    fn supported_msaa(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        requested: Msaa,
+    ) -> Msaa {
+        let format_flags = |format: wgpu::TextureFormat| {
+            if device
+                .features()
+                .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            {
+                adapter.get_texture_format_features(format).flags
+            } else {
+                format.describe().guaranteed_format_features.flags
+            }
+        };
+        let color_flags = format_flags(color_format);
+        let depth_flags = format_flags(Texture::DEPTH_FORMAT);
 
-        Ok(())
+        let mut msaa = requested;
+        while msaa != Msaa::Off
+            && !(color_flags.sample_count_supported(msaa.sample_count())
+                && depth_flags.sample_count_supported(msaa.sample_count()))
+        {
+            msaa = msaa.lower();
+        }
+        if msaa != requested {
+            tracing::warn!(
+                "{:?} msaa is not supported, falling back to {:?}",
+                requested,
+                msaa
+            );
+        }
+        msaa
     }
 
-    /// assumes the entry points of the shader are vs_main and fs_main respectively
+    /// assumes the entry points of the shader are vs_main and fs_main respectively. with msaa
+    /// enabled fs_main_a2c is used instead, which outputs coverage for alpha-to-coverage rather
+    /// than discarding alpha-tested fragments.
     fn create_render_pipeline(
         device: &wgpu::Device,
         layout: &wgpu::PipelineLayout,
         shader_source: &str,
+        target: RenderTarget,
     ) -> wgpu::RenderPipeline {
+        let RenderTarget {
+            color_format, msaa, ..
+        } = target;
         let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader_module"),
             source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(shader_source)),
@@ -337,18 +1038,27 @@ impl Renderer {
                 conservative: false,
             },
             depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
+                format: Texture::DEPTH_FORMAT,
                 depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
+                // equal depths pass, so fragments already written by the depth prepass get shaded
+                depth_compare: target.depth_compare(true),
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
-            multisample: wgpu::MultisampleState::default(),
+            multisample: wgpu::MultisampleState {
+                count: msaa.sample_count(),
+                mask: !0,
+                alpha_to_coverage_enabled: msaa != Msaa::Off,
+            },
             fragment: Some(wgpu::FragmentState {
                 module: &shader_module,
-                entry_point: "fs_main",
+                entry_point: if msaa == Msaa::Off {
+                    "fs_main"
+                } else {
+                    "fs_main_a2c"
+                },
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                    format: color_format,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -357,6 +1067,47 @@ impl Renderer {
         })
     }
 
+    /// depth only pipeline using the vertex stage of the main shader and fs_depth for alpha
+    /// testing
+    fn create_depth_prepass_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader_source: &str,
+        target: RenderTarget,
+    ) -> wgpu::RenderPipeline {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("depth_prepass_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(shader_source)),
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("depth_prepass_pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::vertex_buffer_layout()],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: target.depth_compare(false),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: target.msaa.sample_count(),
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_depth",
+                targets: &[],
+            }),
+            multiview: None,
+        })
+    }
+
     fn create_vertex_buffer(device: &wgpu::Device, vertices: &[Vertex]) -> wgpu::Buffer {
         device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("vertex_buffer"),
@@ -376,6 +1127,10 @@ impl Renderer {
 
 struct RendererCamera {
     pub(super) camera: Camera,
+    /// the camera at the previous simulation step, rendering interpolates between the two
+    pub(super) previous: Camera,
+    /// the interpolated camera of the last frame
+    pub(super) rendered: Camera,
     pub(super) uniform: CameraUniform,
     pub(super) buffer: wgpu::Buffer,
     pub(super) bind_group_layout: wgpu::BindGroupLayout,
@@ -395,7 +1150,7 @@ impl RendererCamera {
             label: Some("camera_bind_group_layout"),
             entries: &[wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -414,6 +1169,8 @@ impl RendererCamera {
         });
 
         Self {
+            previous: camera.clone(),
+            rendered: camera.clone(),
             camera,
             uniform,
             buffer,
@@ -422,9 +1179,140 @@ impl RendererCamera {
         }
     }
 
+    /// orthographic projections keep their height and widen with the aspect ratio like
+    /// perspective ones do
     pub fn set_aspect_ratio(&mut self, queue: &wgpu::Queue, aspect_ratio: f32) {
         self.camera.aspect = aspect_ratio;
+        self.previous.aspect = aspect_ratio;
         self.uniform.update(&self.camera);
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]))
     }
 }
+
+/// light, fog and sky shared by everything drawn into the main pass
+struct RendererEnvironment {
+    pub(super) light: Light,
+    pub(super) fog: Fog,
+    pub(super) light_uniform: LightUniform,
+    pub(super) fog_uniform: FogUniform,
+    pub(super) light_buffer: wgpu::Buffer,
+    pub(super) fog_buffer: wgpu::Buffer,
+    pub(super) bind_group_layout: wgpu::BindGroupLayout,
+    pub(super) bind_group: wgpu::BindGroup,
+}
+
+impl RendererEnvironment {
+    pub fn new(
+        device: &wgpu::Device,
+        bind_group_layout: wgpu::BindGroupLayout,
+        light: Light,
+        fog: Fog,
+        sky: &Sky,
+        skybox: &Skybox,
+    ) -> Self {
+        let mut light_uniform = LightUniform::new();
+        light_uniform.update(&light);
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("light_buffer"),
+            contents: bytemuck::cast_slice(&[light_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let fog_uniform = FogUniform::new();
+        let fog_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("fog_buffer"),
+            contents: bytemuck::cast_slice(&[fog_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("environment_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: fog_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: sky.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&skybox.texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&skybox.texture.sampler),
+                },
+            ],
+        });
+
+        Self {
+            light,
+            fog,
+            light_uniform,
+            fog_uniform,
+            light_buffer,
+            fog_buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// light at binding 0, fog at 1, the sky uniform at 2 and the skybox cube texture and its
+    /// sampler at 3 and 4
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let uniform = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("environment_bind_group_layout"),
+            entries: &[
+                uniform(0),
+                uniform(1),
+                uniform(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, far: f32, procedural_sky: bool) {
+        self.light_uniform.update(&self.light);
+        self.fog_uniform.update(&self.fog, far, procedural_sky);
+        queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::cast_slice(&[self.light_uniform]),
+        );
+        queue.write_buffer(
+            &self.fog_buffer,
+            0,
+            bytemuck::cast_slice(&[self.fog_uniform]),
+        );
+    }
+}
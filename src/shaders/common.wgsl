@@ -0,0 +1,182 @@
+// shared between all shaders, prepended to their source when the shader modules are created
+
+struct CameraUniform {
+    view_projection_matrix: mat4x4<f32>,
+    inverse_view_projection_matrix: mat4x4<f32>,
+    // w is the depth of the far plane, 0.0 with reversed z
+    position: vec4<f32>,
+}
+
+struct LightUniform {
+    direction: vec4<f32>,
+    color: vec4<f32>,
+    ambient: vec4<f32>,
+}
+
+struct FogUniform {
+    // w is 1.0 if the fog color is taken from the sky in view direction
+    color: vec4<f32>,
+    density: f32,
+    start: f32,
+    height: f32,
+    height_density: f32,
+    height_falloff: f32,
+    far: f32,
+    strength: f32,
+    procedural_sky: f32,
+}
+
+struct SkyUniform {
+    // w is the daylight factor
+    sun_direction: vec4<f32>,
+    // w is the night factor
+    moon_direction: vec4<f32>,
+    turbidity: f32,
+    exposure: f32,
+}
+
+struct SsaoUniform {
+    // hemisphere samples in tangent space, the normal being +z
+    kernel: array<vec4<f32>, 32>,
+    // size of the depth texture
+    screen_size: vec2<f32>,
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    sample_count: u32,
+    half_resolution: u32,
+    enabled: u32,
+}
+
+const PI: f32 = 3.14159265;
+
+struct FullscreenVertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// fullscreen triangle without any vertex buffers, drawn with three vertices
+fn fullscreen_vertex(vertex_index: u32, depth: f32) -> FullscreenVertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    let ndc = uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    var out: FullscreenVertexOutput;
+    out.clip_position = vec4<f32>(ndc, depth, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+// unprojects two depths in between the planes, since an infinite far plane can't be
+// unprojected and orthographic rays don't start at the eye
+fn view_direction(camera: CameraUniform, ndc: vec2<f32>) -> vec3<f32> {
+    let near_depth = 1.0 - camera.position.w;
+    let near = camera.inverse_view_projection_matrix
+        * vec4<f32>(ndc, mix(near_depth, camera.position.w, 0.25), 1.0);
+    let far = camera.inverse_view_projection_matrix
+        * vec4<f32>(ndc, mix(near_depth, camera.position.w, 0.75), 1.0);
+    return normalize(far.xyz / far.w - near.xyz / near.w);
+}
+
+// perez et al. sky luminance distribution
+fn perez(theta: f32, gamma: f32, a: f32, b: f32, c: f32, d: f32, e: f32) -> f32 {
+    let cos_gamma = cos(gamma);
+    return (1.0 + a * exp(b / max(cos(theta), 0.01))) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn xyY_to_linear_rgb(x: f32, y: f32, luminance: f32) -> vec3<f32> {
+    let xyz = vec3<f32>(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+    return vec3<f32>(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    );
+}
+
+// preetham et al. "a practical analytic model for daylight"
+fn preetham(direction: vec3<f32>, sun: vec3<f32>, turbidity: f32) -> vec3<f32> {
+    let t = turbidity;
+    let theta_s = acos(clamp(sun.y, 0.0, 1.0));
+    let theta = acos(clamp(direction.y, 0.0, 1.0));
+    let gamma = acos(clamp(dot(direction, sun), -1.0, 1.0));
+
+    let theta_s2 = theta_s * theta_s;
+    let theta_s3 = theta_s2 * theta_s;
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let zenith_luminance = (4.0453 * t - 4.9710) * tan(chi) - 0.2155 * t + 2.4192;
+    let zenith_x = t * t * (0.00166 * theta_s3 - 0.00375 * theta_s2 + 0.00209 * theta_s)
+        + t * (-0.02903 * theta_s3 + 0.06377 * theta_s2 - 0.03202 * theta_s + 0.00394)
+        + (0.11693 * theta_s3 - 0.21196 * theta_s2 + 0.06052 * theta_s + 0.25886);
+    let zenith_y = t * t * (0.00275 * theta_s3 - 0.00610 * theta_s2 + 0.00317 * theta_s)
+        + t * (-0.04214 * theta_s3 + 0.08970 * theta_s2 - 0.04153 * theta_s + 0.00516)
+        + (0.15346 * theta_s3 - 0.26756 * theta_s2 + 0.06670 * theta_s + 0.26688);
+
+    let luminance = zenith_luminance
+        * perez(theta, gamma, 0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703)
+        / perez(0.0, theta_s, 0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703);
+    let x = zenith_x
+        * perez(theta, gamma, -0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452)
+        / perez(0.0, theta_s, -0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452);
+    let y = zenith_y
+        * perez(theta, gamma, -0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529)
+        / perez(0.0, theta_s, -0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529);
+
+    return max(xyY_to_linear_rgb(x, y, luminance), vec3<f32>(0.0));
+}
+
+// sky color without sun, moon and stars, so it can be used for fog and ambient light
+fn sky_radiance(direction: vec3<f32>, sky: SkyUniform) -> vec3<f32> {
+    let daylight = sky.sun_direction.w;
+    let night = sky.moon_direction.w;
+    // the model is only defined for a sun above the horizon, below it the sky fades to night
+    let sun = normalize(vec3<f32>(sky.sun_direction.x, max(sky.sun_direction.y, 0.02), sky.sun_direction.z));
+    let horizon = smoothstep(-0.05, 0.05, direction.y);
+
+    var color = preetham(direction, sun, sky.turbidity) * sky.exposure * daylight;
+    color += vec3<f32>(0.004, 0.008, 0.02) * night;
+    // darken everything below the horizon towards the ground
+    return color * mix(0.3, 1.0, horizon);
+}
+
+// exponential distance fog combined with height fog, whose density falls off exponentially above
+// fog.height. the height fog is integrated analytically along the view ray. everything reaches
+// full fog at the far plane, so geometry doesn't end abruptly.
+fn fog_factor(fog: FogUniform, camera_position: vec3<f32>, world_position: vec3<f32>) -> f32 {
+    if fog.strength <= 0.0 {
+        return 0.0;
+    }
+    let to_point = world_position - camera_position;
+    let distance = length(to_point);
+    let fogged_distance = max(distance - fog.start, 0.0);
+
+    let distance_term = fog.density * fogged_distance;
+
+    let falloff = fog.height_falloff * to_point.y / max(distance, 0.0001) * fogged_distance;
+    var integral = 1.0;
+    if abs(falloff) > 0.0001 {
+        integral = (1.0 - exp(-falloff)) / falloff;
+    }
+    let height_term = fog.height_density * exp(-fog.height_falloff * (camera_position.y - fog.height))
+        * integral * fogged_distance;
+
+    let factor = 1.0 - exp(-(distance_term + height_term));
+    return clamp(max(factor, smoothstep(fog.far * 0.8, fog.far, distance)), 0.0, 1.0) * fog.strength;
+}
+
+fn apply_fog(color: vec3<f32>, fog_color: vec3<f32>, factor: f32) -> vec3<f32> {
+    return mix(color, fog_color, factor);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
@@ -0,0 +1,86 @@
+// builds a histogram of the log luminance of the hdr target and averages it into the adapted
+// scene luminance used for auto exposure
+
+struct TonemapUniform {
+    tonemapper: u32,
+    auto_exposure: u32,
+    _padding: u32,
+    exposure: f32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation_speed: f32,
+    time_delta: f32,
+}
+
+struct AutoExposure {
+    luminance: f32,
+}
+
+const BIN_COUNT: u32 = 256u;
+
+@group(0) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> params: TonemapUniform;
+@group(0) @binding(2)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(3)
+var<storage, read_write> auto_exposure: AutoExposure;
+
+var<workgroup> local_histogram: array<atomic<u32>, 256>;
+var<workgroup> weighted_bins: array<f32, 256>;
+
+// bin 0 is reserved for (almost) black pixels, which are excluded from the average
+fn luminance_bin(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if luminance < 0.0001 {
+        return 0u;
+    }
+    let t = clamp((log2(luminance) - params.min_log_luminance) / params.log_luminance_range, 0.0, 1.0);
+    return u32(t * 254.0 + 1.0);
+}
+
+@compute @workgroup_size(16, 16)
+fn build_histogram(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    atomicStore(&local_histogram[local_index], 0u);
+    workgroupBarrier();
+
+    let size = vec2<u32>(textureDimensions(t_hdr));
+    if global_id.x < size.x && global_id.y < size.y {
+        let color = textureLoad(t_hdr, vec2<i32>(global_id.xy), 0).rgb;
+        atomicAdd(&local_histogram[luminance_bin(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[local_index], atomicLoad(&local_histogram[local_index]));
+}
+
+@compute @workgroup_size(256)
+fn average_histogram(@builtin(local_invocation_index) local_index: u32) {
+    let count = atomicLoad(&histogram[local_index]);
+    weighted_bins[local_index] = f32(count) * f32(local_index);
+    // cleared for the next frame
+    atomicStore(&histogram[local_index], 0u);
+    workgroupBarrier();
+
+    for (var stride = BIN_COUNT / 2u; stride > 0u; stride = stride >> 1u) {
+        if local_index < stride {
+            weighted_bins[local_index] += weighted_bins[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    // thread 0 holds the count of the black bin
+    if local_index == 0u {
+        let size = vec2<u32>(textureDimensions(t_hdr));
+        let lit_pixels = max(f32(size.x * size.y) - f32(count), 1.0);
+        let average_bin = weighted_bins[0] / lit_pixels;
+        let log_luminance = (average_bin - 1.0) / 254.0 * params.log_luminance_range + params.min_log_luminance;
+        let target_luminance = exp2(log_luminance);
+        let adaptation = 1.0 - exp(-params.time_delta * params.adaptation_speed);
+        auto_exposure.luminance += (target_luminance - auto_exposure.luminance) * adaptation;
+    }
+}
@@ -0,0 +1,162 @@
+struct PostProcessUniform {
+    bloom_threshold: f32,
+    bloom_knee: f32,
+    color_grading_contribution: f32,
+    lut_size: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    vignette_smoothness: f32,
+    film_grain_intensity: f32,
+    sharpen_strength: f32,
+    time: f32,
+    // the surface format doesn't encode to srgb itself
+    encode_srgb: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(2)
+var<uniform> params: PostProcessUniform;
+
+// only bound for color grading
+@group(1) @binding(0)
+var t_lut: texture_3d<f32>;
+@group(1) @binding(1)
+var s_lut: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> FullscreenVertexOutput {
+    return fullscreen_vertex(vertex_index, 0.0);
+}
+
+fn screen_uv(ndc: vec2<f32>) -> vec2<f32> {
+    return ndc * vec2<f32>(0.5, -0.5) + 0.5;
+}
+
+fn source_texel_size() -> vec2<f32> {
+    return 1.0 / vec2<f32>(textureDimensions(t_source));
+}
+
+// 13 tap downsample from the call of duty: advanced warfare bloom, weighting the overlapping
+// 2x2 boxes so the result doesn't flicker
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let t = source_texel_size();
+    let a = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(-2.0, -2.0), 0.0).rgb;
+    let b = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(0.0, -2.0), 0.0).rgb;
+    let c = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(2.0, -2.0), 0.0).rgb;
+    let d = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(-2.0, 0.0), 0.0).rgb;
+    let e = textureSampleLevel(t_source, s_source, uv, 0.0).rgb;
+    let f = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(2.0, 0.0), 0.0).rgb;
+    let g = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(-2.0, 2.0), 0.0).rgb;
+    let h = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(0.0, 2.0), 0.0).rgb;
+    let i = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(2.0, 2.0), 0.0).rgb;
+    let j = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(-1.0, -1.0), 0.0).rgb;
+    let k = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(1.0, -1.0), 0.0).rgb;
+    let l = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(-1.0, 1.0), 0.0).rgb;
+    let m = textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(1.0, 1.0), 0.0).rgb;
+    return e * 0.125 + (a + c + g + i) * 0.03125 + (b + d + f + h) * 0.0625 + (j + k + l + m) * 0.125;
+}
+
+// quadratic soft threshold, so pixels slightly below the threshold still bloom a little
+fn threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = params.bloom_threshold * params.bloom_knee;
+    var soft = clamp(brightness - params.bloom_threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.00001);
+    let contribution = max(soft, brightness - params.bloom_threshold) / max(brightness, 0.00001);
+    return color * contribution;
+}
+
+@fragment
+fn fs_bloom_prefilter(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(threshold(downsample(screen_uv(in.ndc))), 1.0);
+}
+
+@fragment
+fn fs_bloom_downsample(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(screen_uv(in.ndc)), 1.0);
+}
+
+// 3x3 tent filter, blended additively onto the next larger mip
+@fragment
+fn fs_bloom_upsample(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let uv = screen_uv(in.ndc);
+    let t = source_texel_size();
+    var color = textureSampleLevel(t_source, s_source, uv, 0.0).rgb * 4.0;
+    color += textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(-1.0, 0.0), 0.0).rgb * 2.0;
+    color += textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(1.0, 0.0), 0.0).rgb * 2.0;
+    color += textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(0.0, -1.0), 0.0).rgb * 2.0;
+    color += textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(0.0, 1.0), 0.0).rgb * 2.0;
+    color += textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(-1.0, -1.0), 0.0).rgb;
+    color += textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(1.0, -1.0), 0.0).rgb;
+    color += textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(-1.0, 1.0), 0.0).rgb;
+    color += textureSampleLevel(t_source, s_source, uv + t * vec2<f32>(1.0, 1.0), 0.0).rgb;
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+// the lut is authored for display encoded colors, so the lookup happens in srgb space
+@fragment
+fn fs_color_grading(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(t_source, vec2<i32>(in.clip_position.xy), 0).rgb;
+    let encoded = linear_to_srgb(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)));
+    // remap onto the texel centers of the outermost lut entries
+    let scale = (params.lut_size - 1.0) / params.lut_size;
+    let offset = 0.5 / params.lut_size;
+    let graded = textureSampleLevel(t_lut, s_lut, encoded * scale + offset, 0.0).rgb;
+    return vec4<f32>(mix(color, srgb_to_linear(graded), params.color_grading_contribution), 1.0);
+}
+
+@fragment
+fn fs_vignette(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(t_source, vec2<i32>(in.clip_position.xy), 0).rgb;
+    let size = vec2<f32>(textureDimensions(t_source));
+    // keep the vignette round regardless of the aspect ratio
+    let offset = (screen_uv(in.ndc) - 0.5) * vec2<f32>(size.x / size.y, 1.0);
+    let falloff = smoothstep(params.vignette_radius, params.vignette_radius + params.vignette_smoothness, length(offset));
+    return vec4<f32>(color * (1.0 - params.vignette_intensity * falloff), 1.0);
+}
+
+fn hash(p: vec3<f32>) -> f32 {
+    var q = fract(p * 0.1031);
+    q += dot(q, q.zyx + 31.32);
+    return fract((q.x + q.y) * q.z);
+}
+
+// grain is added in display space so it is equally visible in shadows and highlights, and
+// fades out towards pure black and white
+@fragment
+fn fs_film_grain(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(t_source, vec2<i32>(in.clip_position.xy), 0).rgb;
+    let encoded = linear_to_srgb(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let noise = hash(vec3<f32>(in.clip_position.xy, fract(params.time) * 1000.0)) - 0.5;
+    let l = luminance(encoded);
+    let grain = noise * params.film_grain_intensity * 4.0 * l * (1.0 - l);
+    return vec4<f32>(srgb_to_linear(clamp(encoded + grain, vec3<f32>(0.0), vec3<f32>(1.0))), 1.0);
+}
+
+// unsharp mask with a cross shaped kernel
+@fragment
+fn fs_sharpen(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let position = vec2<i32>(in.clip_position.xy);
+    let max_position = vec2<i32>(textureDimensions(t_source)) - 1;
+    let center = textureLoad(t_source, position, 0).rgb;
+    let left = textureLoad(t_source, clamp(position + vec2<i32>(-1, 0), vec2<i32>(0), max_position), 0).rgb;
+    let right = textureLoad(t_source, clamp(position + vec2<i32>(1, 0), vec2<i32>(0), max_position), 0).rgb;
+    let up = textureLoad(t_source, clamp(position + vec2<i32>(0, -1), vec2<i32>(0), max_position), 0).rgb;
+    let down = textureLoad(t_source, clamp(position + vec2<i32>(0, 1), vec2<i32>(0), max_position), 0).rgb;
+    let sharpened = center + (4.0 * center - left - right - up - down) * params.sharpen_strength;
+    return vec4<f32>(max(sharpened, vec3<f32>(0.0)), 1.0);
+}
+
+// copies the result of the chain into the surface
+@fragment
+fn fs_output(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    var color = textureLoad(t_source, vec2<i32>(in.clip_position.xy), 0).rgb;
+    if params.encode_srgb != 0u {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
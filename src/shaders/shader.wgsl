@@ -6,10 +6,7 @@ struct VertexInput {
 struct VertexOuput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
-}
-
-struct CameraUniform {
-    view_projection_matrix: mat4x4<f32>
+    @location(1) world_position: vec3<f32>,
 }
 
 @group(1) @binding(0)
@@ -20,6 +17,7 @@ fn vs_main(in: VertexInput) -> VertexOuput {
     var out: VertexOuput;
     out.clip_position = camera.view_projection_matrix * vec4<f32>(in.position, 1.0);
     out.tex_coords = in.tex_coords;
+    out.world_position = in.position;
     return out;
 }
 
@@ -28,7 +26,78 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+@group(2) @binding(0)
+var<uniform> light: LightUniform;
+@group(2) @binding(1)
+var<uniform> fog: FogUniform;
+@group(2) @binding(2)
+var<uniform> sky: SkyUniform;
+@group(2) @binding(3)
+var t_environment: texture_cube<f32>;
+@group(2) @binding(4)
+var s_environment: sampler;
+
+@group(3) @binding(0)
+var t_ao: texture_2d<f32>;
+@group(3) @binding(1)
+var s_ao: sampler;
+@group(3) @binding(2)
+var<uniform> ssao: SsaoUniform;
+
+const ALPHA_CUTOFF: f32 = 0.5;
+
+fn sky_color(direction: vec3<f32>) -> vec3<f32> {
+    if fog.procedural_sky > 0.5 {
+        return sky_radiance(direction, sky);
+    }
+    return textureSampleLevel(t_environment, s_environment, direction, 0.0).rgb;
+}
+
+fn ambient_occlusion(frag_position: vec2<f32>) -> f32 {
+    if ssao.enabled == 0u {
+        return 1.0;
+    }
+    return textureSampleLevel(t_ao, s_ao, frag_position / ssao.screen_size, 0.0).r;
+}
+
+fn shade(color: vec3<f32>, world_position: vec3<f32>, frag_position: vec2<f32>) -> vec3<f32> {
+    // meshes don't carry normals, so the flat face normal is reconstructed from the screen
+    // space derivatives of the world position and turned towards the camera
+    var normal = normalize(cross(dpdx(world_position), dpdy(world_position)));
+    if dot(normal, camera.position.xyz - world_position) < 0.0 {
+        normal = -normal;
+    }
+    let diffuse = max(dot(normal, -light.direction.xyz), 0.0);
+    let ambient = light.ambient.rgb * ambient_occlusion(frag_position);
+    let lit = color * (ambient + light.color.rgb * diffuse);
+
+    let fog_color = mix(fog.color.rgb, sky_color(normalize(world_position - camera.position.xyz)), fog.color.w);
+    return apply_fog(lit, fog_color, fog_factor(fog, camera.position.xyz, world_position));
+}
+
 @fragment
 fn fs_main(in: VertexOuput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let shaded = shade(color.rgb, in.world_position, in.clip_position.xy);
+    if color.a < ALPHA_CUTOFF {
+        discard;
+    }
+    return vec4<f32>(shaded, 1.0);
+}
+
+// sharpens the alpha around the cutoff to roughly one pixel, so alpha-to-coverage produces
+// antialiased edges instead of dithering the whole alpha gradient
+@fragment
+fn fs_main_a2c(in: VertexOuput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let alpha = (color.a - ALPHA_CUTOFF) / max(fwidth(color.a), 0.0001) + 0.5;
+    return vec4<f32>(shade(color.rgb, in.world_position, in.clip_position.xy), clamp(alpha, 0.0, 1.0));
+}
+
+// depth prepass, only alpha testing
+@fragment
+fn fs_depth(in: VertexOuput) {
+    if textureSample(t_diffuse, s_diffuse, in.tex_coords).a < ALPHA_CUTOFF {
+        discard;
+    }
 }
@@ -0,0 +1,47 @@
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(1)
+var<uniform> fog: FogUniform;
+@group(1) @binding(2)
+var<uniform> sky: SkyUniform;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> FullscreenVertexOutput {
+    // at the far plane
+    return fullscreen_vertex(vertex_index, camera.position.w);
+}
+
+const SUN_ANGULAR_RADIUS: f32 = 0.0093;
+const MOON_ANGULAR_RADIUS: f32 = 0.009;
+
+fn hash(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3<f32>(12.9898, 78.233, 45.164))) * 43758.5453);
+}
+
+fn stars(direction: vec3<f32>) -> vec3<f32> {
+    let cell = floor(direction * 300.0);
+    let brightness = step(0.997, hash(cell)) * hash(cell + 1.0);
+    return vec3<f32>(brightness);
+}
+
+@fragment
+fn fs_main(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let direction = view_direction(camera, in.ndc);
+    let daylight = sky.sun_direction.w;
+    let night = sky.moon_direction.w;
+    let horizon = smoothstep(-0.05, 0.05, direction.y);
+
+    let background = sky_radiance(direction, sky);
+    var color = background;
+    let sun_disk = step(cos(SUN_ANGULAR_RADIUS), dot(direction, sky.sun_direction.xyz));
+    color += vec3<f32>(20.0) * sun_disk * daylight * horizon;
+    color += stars(direction) * night * horizon;
+    let moon_angle = dot(direction, sky.moon_direction.xyz);
+    color += vec3<f32>(0.8, 0.85, 0.9) * step(cos(MOON_ANGULAR_RADIUS), moon_angle) * night * horizon;
+    color += vec3<f32>(0.05, 0.06, 0.08) * pow(max(moon_angle, 0.0), 64.0) * night;
+
+    let fog_color = mix(fog.color.rgb, background, fog.color.w);
+    let factor = fog_factor(fog, camera.position.xyz, camera.position.xyz + direction * fog.far);
+    return vec4<f32>(apply_fog(color, fog_color, factor), 1.0);
+}
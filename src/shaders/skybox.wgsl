@@ -0,0 +1,24 @@
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> FullscreenVertexOutput {
+    // at the far plane
+    return fullscreen_vertex(vertex_index, camera.position.w);
+}
+
+@group(1) @binding(1)
+var<uniform> fog: FogUniform;
+@group(1) @binding(3)
+var t_environment: texture_cube<f32>;
+@group(1) @binding(4)
+var s_environment: sampler;
+
+@fragment
+fn fs_main(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let direction = view_direction(camera, in.ndc);
+    let color = textureSample(t_environment, s_environment, direction).rgb;
+    let fog_color = mix(fog.color.rgb, color, fog.color.w);
+    let factor = fog_factor(fog, camera.position.xyz, camera.position.xyz + direction * fog.far);
+    return vec4<f32>(apply_fog(color, fog_color, factor), 1.0);
+}
@@ -0,0 +1,126 @@
+// DepthTexture is declared in front of this file, depending on whether the depth texture is
+// multisampled
+
+const MAX_KERNEL_SIZE: u32 = 32u;
+const BLUR_RADIUS: i32 = 4;
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var t_depth: DepthTexture;
+@group(1) @binding(1)
+var<uniform> ssao: SsaoUniform;
+
+// only bound for the blur passes
+@group(2) @binding(0)
+var t_source: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> FullscreenVertexOutput {
+    return fullscreen_vertex(vertex_index, 0.0);
+}
+
+// the ao targets may be half the size of the depth texture
+fn depth_coords(position: vec2<f32>) -> vec2<i32> {
+    let scale = select(1.0, 2.0, ssao.half_resolution != 0u);
+    return clamp(vec2<i32>(position * scale), vec2<i32>(0), vec2<i32>(ssao.screen_size) - 1);
+}
+
+fn world_position(coords: vec2<i32>) -> vec3<f32> {
+    let clamped = clamp(coords, vec2<i32>(0), vec2<i32>(ssao.screen_size) - 1);
+    let depth = textureLoad(t_depth, clamped, 0);
+    let uv = (vec2<f32>(clamped) + 0.5) / ssao.screen_size;
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let world = camera.inverse_view_projection_matrix * vec4<f32>(ndc, depth, 1.0);
+    return world.xyz / world.w;
+}
+
+// takes the neighbour closer to the center on each axis, so normals don't smear across depth
+// discontinuities
+fn reconstruct_normal(coords: vec2<i32>, center: vec3<f32>) -> vec3<f32> {
+    let left = center - world_position(coords - vec2<i32>(1, 0));
+    let right = world_position(coords + vec2<i32>(1, 0)) - center;
+    let up = center - world_position(coords - vec2<i32>(0, 1));
+    let down = world_position(coords + vec2<i32>(0, 1)) - center;
+    let dx = select(left, right, dot(right, right) < dot(left, left));
+    let dy = select(up, down, dot(down, down) < dot(up, up));
+    var normal = normalize(cross(dx, dy));
+    if dot(normal, camera.position.xyz - center) < 0.0 {
+        normal = -normal;
+    }
+    return normal;
+}
+
+fn interleaved_gradient_noise(position: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(position, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_ssao(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let coords = depth_coords(in.clip_position.xy);
+    if textureLoad(t_depth, coords, 0) == camera.position.w {
+        // nothing to occlude the sky
+        return vec4<f32>(1.0);
+    }
+    let position = world_position(coords);
+    let normal = reconstruct_normal(coords, position);
+
+    // rotate the kernel around the normal by a per pixel random angle, the blur hides the noise
+    let helper = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(normal.y) > 0.99);
+    let tangent_base = normalize(cross(helper, normal));
+    let bitangent_base = cross(normal, tangent_base);
+    let angle = interleaved_gradient_noise(in.clip_position.xy) * 2.0 * PI;
+    let tangent = cos(angle) * tangent_base + sin(angle) * bitangent_base;
+    let tbn = mat3x3<f32>(tangent, cross(normal, tangent), normal);
+
+    let sample_count = min(ssao.sample_count, MAX_KERNEL_SIZE);
+    var occlusion = 0.0;
+    for (var i = 0u; i < sample_count; i++) {
+        let sample_position = position + tbn * ssao.kernel[i].xyz * ssao.radius;
+        let clip = camera.view_projection_matrix * vec4<f32>(sample_position, 1.0);
+        let ndc = clip.xy / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+            continue;
+        }
+        let scene_position = world_position(vec2<i32>(uv * ssao.screen_size));
+        let scene_distance = distance(camera.position.xyz, scene_position);
+        let sample_distance = distance(camera.position.xyz, sample_position);
+        // occluders much further away than the radius don't contribute
+        let range = smoothstep(0.0, 1.0, ssao.radius / max(distance(position, scene_position), 0.0001));
+        occlusion += select(0.0, range, scene_distance <= sample_distance - ssao.bias);
+    }
+    let ao = 1.0 - occlusion / f32(max(sample_count, 1u));
+    return vec4<f32>(pow(ao, ssao.intensity), 0.0, 0.0, 1.0);
+}
+
+// gaussian weights attenuated by the relative difference in view distance, so occlusion doesn't
+// bleed over edges
+fn bilateral_blur(position: vec2<f32>, direction: vec2<i32>) -> f32 {
+    let coords = vec2<i32>(position);
+    let max_coords = vec2<i32>(textureDimensions(t_source)) - 1;
+    let center_distance = distance(camera.position.xyz, world_position(depth_coords(position)));
+    var sum = 0.0;
+    var weight_sum = 0.0;
+    for (var i = -BLUR_RADIUS; i <= BLUR_RADIUS; i++) {
+        let sample_coords = clamp(coords + direction * i, vec2<i32>(0), max_coords);
+        let sample_distance = distance(camera.position.xyz, world_position(depth_coords(vec2<f32>(sample_coords) + 0.5)));
+        let spatial = exp(-f32(i * i) / (2.0 * 4.0));
+        let range = exp(-abs(sample_distance - center_distance) / max(center_distance * 0.05, 0.0001));
+        let weight = spatial * range;
+        sum += textureLoad(t_source, sample_coords, 0).r * weight;
+        weight_sum += weight;
+    }
+    return sum / max(weight_sum, 0.0001);
+}
+
+@fragment
+fn fs_blur_horizontal(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(bilateral_blur(in.clip_position.xy, vec2<i32>(1, 0)), 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_blur_vertical(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(bilateral_blur(in.clip_position.xy, vec2<i32>(0, 1)), 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,100 @@
+struct TonemapUniform {
+    tonemapper: u32,
+    auto_exposure: u32,
+    _padding: u32,
+    exposure: f32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation_speed: f32,
+    time_delta: f32,
+}
+
+struct AutoExposure {
+    // adapted average scene luminance
+    luminance: f32,
+}
+
+// middle gray the average luminance is mapped to with auto exposure
+const KEY_VALUE: f32 = 0.18;
+
+@group(0) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> params: TonemapUniform;
+@group(0) @binding(2)
+var<storage, read> auto_exposure: AutoExposure;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> FullscreenVertexOutput {
+    return fullscreen_vertex(vertex_index, 0.0);
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// narkowicz's fit of the aces filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// polynomial approximation of the agx default contrast curve by benjamin wrensch
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    );
+    let outset = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var c = inset * color;
+    c = clamp(log2(max(c, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    c = (c - min_ev) / (max_ev - min_ev);
+    c = agx_contrast(c);
+    c = outset * c;
+    // the curve outputs display encoded values, decode them back to linear
+    return pow(max(c, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs_main(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let hdr = textureLoad(t_hdr, vec2<i32>(in.clip_position.xy), 0).rgb;
+
+    var exposure = params.exposure;
+    if params.auto_exposure != 0u {
+        exposure *= KEY_VALUE / max(auto_exposure.luminance, 0.0001);
+    }
+    let color = hdr * exposure;
+
+    // matches the order of the Tonemapper enum
+    var mapped: vec3<f32>;
+    switch params.tonemapper {
+        case 1u: {
+            mapped = aces(color);
+        }
+        case 2u: {
+            mapped = agx(color);
+        }
+        default: {
+            mapped = reinhard(color);
+        }
+    }
+    return vec4<f32>(mapped, 1.0);
+}
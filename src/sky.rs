@@ -0,0 +1,283 @@
+use std::time::Duration;
+
+use cgmath::{InnerSpace, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    input_manager::{InputManager, KeyCode},
+    light::Light,
+    renderer::RenderTarget,
+    texture::Texture,
+};
+
+/// advances the time of day and derives the sun and moon positions from it
+///
+/// T pauses and resumes the cycle, the left and right arrow keys scrub through the day.
+#[derive(Debug, Clone)]
+pub struct DayNightCycle {
+    /// fraction of a day, 0.0 is midnight and 0.5 is noon
+    time_of_day: f32,
+    day_length: Duration,
+    paused: bool,
+    /// days per second while scrubbing
+    scrub_speed: f32,
+}
+
+impl DayNightCycle {
+    pub fn new(day_length: Duration) -> Self {
+        Self {
+            time_of_day: 0.35,
+            day_length,
+            paused: false,
+            scrub_speed: 0.1,
+        }
+    }
+
+    /// has to be called once per frame, unlike `update` which may run several times
+    pub fn handle_input(&mut self, input: &InputManager) {
+        if input.is_key_just_pressed(KeyCode::T) {
+            self.paused = !self.paused
+        }
+    }
+
+    pub fn update(&mut self, input: &InputManager, time_delta: Duration) {
+        let time_delta = time_delta.as_secs_f32();
+
+        if input.is_key_pressed(KeyCode::Right) {
+            self.time_of_day += self.scrub_speed * time_delta
+        }
+        if input.is_key_pressed(KeyCode::Left) {
+            self.time_of_day -= self.scrub_speed * time_delta
+        }
+        if !self.paused && !self.day_length.is_zero() {
+            self.time_of_day += time_delta / self.day_length.as_secs_f32()
+        }
+        self.time_of_day = self.time_of_day.rem_euclid(1.0)
+    }
+
+    /// blends the time of day towards `next` the short way around midnight
+    pub fn interpolate(&self, next: &DayNightCycle, alpha: f32) -> DayNightCycle {
+        let difference = (next.time_of_day - self.time_of_day + 0.5).rem_euclid(1.0) - 0.5;
+        DayNightCycle {
+            time_of_day: (self.time_of_day + difference * alpha).rem_euclid(1.0),
+            ..next.clone()
+        }
+    }
+
+    pub fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        self.time_of_day = time_of_day.rem_euclid(1.0)
+    }
+
+    pub fn day_length(&self) -> Duration {
+        self.day_length
+    }
+
+    pub fn set_day_length(&mut self, day_length: Duration) {
+        self.day_length = day_length
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused
+    }
+
+    /// unit vector pointing towards the sun. it rises in +x at 6:00 and its path is tilted
+    /// towards -z.
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = (self.time_of_day - 0.25) * std::f32::consts::TAU;
+        Vector3::new(angle.cos(), angle.sin(), -0.35).normalize()
+    }
+
+    /// unit vector pointing towards the moon, which is always opposite to the sun
+    pub fn moon_direction(&self) -> Vector3<f32> {
+        -self.sun_direction()
+    }
+
+    /// 1.0 during the day and 0.0 at night with a smooth transition around sunrise and sunset
+    pub fn daylight(&self) -> f32 {
+        smoothstep(-0.2, 0.2, self.sun_direction().y)
+    }
+
+    /// sunlight during the day and moonlight at night. both fade out towards the horizon, so
+    /// switching between them is seamless.
+    pub fn light(&self) -> Light {
+        let sun = self.sun_direction();
+        let daylight = self.daylight();
+
+        let (direction, color) = if sun.y >= 0.0 {
+            // the lower the sun, the more atmosphere its light travels through, shifting it red
+            let sunset = 1.0 - smoothstep(0.0, 0.35, sun.y);
+            let color = lerp(
+                Vector3::new(1.0, 0.95, 0.88),
+                Vector3::new(1.0, 0.45, 0.15),
+                sunset,
+            );
+            (-sun, color * smoothstep(0.0, 0.1, sun.y))
+        } else {
+            let moon = self.moon_direction();
+            let color = Vector3::new(0.12, 0.15, 0.22);
+            (-moon, color * smoothstep(0.0, 0.1, moon.y))
+        };
+
+        Light {
+            direction,
+            color,
+            ambient: lerp(
+                Vector3::new(0.02, 0.025, 0.05),
+                Vector3::new(0.3, 0.34, 0.42),
+                daylight,
+            ),
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: Vector3<f32>, b: Vector3<f32>, t: f32) -> Vector3<f32> {
+    a + (b - a) * t
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkyUniform {
+    // w is the daylight factor
+    sun_direction: [f32; 4],
+    // w is the night factor, used for moon and star visibility
+    moon_direction: [f32; 4],
+    turbidity: f32,
+    exposure: f32,
+    _padding: [f32; 2],
+}
+
+/// analytic daylight sky after preetham et al. with moon and stars at night, rendered as a
+/// fullscreen pass at the far plane. its uniform buffer is bound as part of the environment bind
+/// group, so other shaders can evaluate the sky as well.
+pub struct Sky {
+    /// haziness of the atmosphere, sensible values are between 2 and 10
+    pub turbidity: f32,
+    pub exposure: f32,
+    uniform: SkyUniform,
+    buffer: wgpu::Buffer,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Sky {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        environment_bind_group_layout: &wgpu::BindGroupLayout,
+        target: RenderTarget,
+    ) -> Self {
+        let uniform = SkyUniform {
+            sun_direction: [0.0, 1.0, 0.0, 1.0],
+            moon_direction: [0.0, -1.0, 0.0, 0.0],
+            turbidity: 2.5,
+            exposure: 0.1,
+            _padding: [0.0; 2],
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("sky_buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sky_pipeline_layout"),
+            bind_group_layouts: &[camera_bind_group_layout, environment_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(device, &pipeline_layout, target);
+
+        Self {
+            turbidity: uniform.turbidity,
+            exposure: uniform.exposure,
+            uniform,
+            buffer,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, cycle: &DayNightCycle) {
+        let daylight = cycle.daylight();
+        self.uniform.sun_direction = cycle.sun_direction().extend(daylight).into();
+        self.uniform.moon_direction = cycle.moon_direction().extend(1.0 - daylight).into();
+        self.uniform.turbidity = self.turbidity;
+        self.uniform.exposure = self.exposure;
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// has to be called when the format or sample count of the render target changes
+    pub fn set_render_target(&mut self, device: &wgpu::Device, target: RenderTarget) {
+        self.pipeline = Self::create_pipeline(device, &self.pipeline_layout, target);
+    }
+
+    /// expects to be drawn after the opaque geometry of the pass, as it only covers pixels still
+    /// at the far plane
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        environment_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, environment_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        target: RenderTarget,
+    ) -> wgpu::RenderPipeline {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sky_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(concat!(
+                include_str!("./shaders/common.wgsl"),
+                include_str!("./shaders/sky.wgsl")
+            ))),
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("sky_pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: target.depth_compare(true),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: target.msaa.sample_count(),
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(target.color_format.into())],
+            }),
+            multiview: None,
+        })
+    }
+}
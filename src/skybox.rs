@@ -0,0 +1,187 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::{
+    renderer::RenderTarget,
+    texture::{Texture, TextureOptions},
+};
+
+/// cube texture rendered behind the scene. the texture is bound as part of the environment bind
+/// group, where the skybox samples it from just like other shaders.
+pub struct Skybox {
+    pub texture: Texture,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Skybox {
+    /// faces in the order +x, -x, +y, -y, +z, -z
+    pub fn from_faces(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        environment_bind_group_layout: &wgpu::BindGroupLayout,
+        target: RenderTarget,
+        faces: &[image::DynamicImage; 6],
+    ) -> anyhow::Result<Self> {
+        let texture = Texture::from_cube_faces(
+            device,
+            queue,
+            &TextureOptions::new().label("skybox_texture"),
+            faces,
+        )?;
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("skybox_pipeline_layout"),
+            bind_group_layouts: &[camera_bind_group_layout, environment_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(device, &pipeline_layout, target);
+
+        Ok(Self {
+            texture,
+            pipeline_layout,
+            pipeline,
+        })
+    }
+
+    pub fn from_equirectangular(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        environment_bind_group_layout: &wgpu::BindGroupLayout,
+        target: RenderTarget,
+        image: &image::DynamicImage,
+    ) -> anyhow::Result<Self> {
+        // a quarter of the panorama's width keeps roughly the same texel density
+        let face_size = (image.width() / 4).max(1);
+        let faces = equirectangular_to_cube_faces(image, face_size);
+        Self::from_faces(
+            device,
+            queue,
+            camera_bind_group_layout,
+            environment_bind_group_layout,
+            target,
+            &faces,
+        )
+    }
+
+    /// simple vertical gradient, used when no sky images are available
+    pub fn from_gradient(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        environment_bind_group_layout: &wgpu::BindGroupLayout,
+        target: RenderTarget,
+        zenith: [u8; 3],
+        horizon: [u8; 3],
+    ) -> anyhow::Result<Self> {
+        let (width, height) = (512, 256);
+        let mix = |a: [u8; 3], b: [u8; 3], t: f32| {
+            let mut color = [255; 4];
+            for i in 0..3 {
+                color[i] = (a[i] as f32 + (b[i] as f32 - a[i] as f32) * t) as u8;
+            }
+            image::Rgba(color)
+        };
+        let image = image::RgbaImage::from_fn(width, height, |_, y| {
+            let latitude = 1.0 - 2.0 * (y as f32 + 0.5) / height as f32;
+            mix(horizon, zenith, latitude.max(0.0).sqrt())
+        });
+        Self::from_equirectangular(
+            device,
+            queue,
+            camera_bind_group_layout,
+            environment_bind_group_layout,
+            target,
+            &image::DynamicImage::ImageRgba8(image),
+        )
+    }
+
+    /// has to be called when the format or sample count of the render target changes
+    pub fn set_render_target(&mut self, device: &wgpu::Device, target: RenderTarget) {
+        self.pipeline = Self::create_pipeline(device, &self.pipeline_layout, target);
+    }
+
+    /// expects to be drawn after the opaque geometry of the pass, as it only covers pixels still
+    /// at the far plane
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        environment_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, environment_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        target: RenderTarget,
+    ) -> wgpu::RenderPipeline {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("skybox_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(concat!(
+                include_str!("./shaders/common.wgsl"),
+                include_str!("./shaders/skybox.wgsl")
+            ))),
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("skybox_pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: target.depth_compare(true),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: target.msaa.sample_count(),
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(target.color_format.into())],
+            }),
+            multiview: None,
+        })
+    }
+}
+
+/// resamples an equirectangular panorama into cube faces in the order +x, -x, +y, -y, +z, -z
+pub fn equirectangular_to_cube_faces(
+    image: &image::DynamicImage,
+    face_size: u32,
+) -> [image::DynamicImage; 6] {
+    let rgba = image.to_rgba8();
+    let face = |direction: fn(f32, f32) -> Vector3<f32>| {
+        let face = image::RgbaImage::from_fn(face_size, face_size, |x, y| {
+            let s = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+            let t = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+            let direction = direction(s, t).normalize();
+            let u = 0.5 + direction.z.atan2(direction.x) / std::f32::consts::TAU;
+            let v = 0.5 - direction.y.asin() / std::f32::consts::PI;
+            image::imageops::sample_bilinear(&rgba, u.clamp(0.0, 1.0), v.clamp(0.0, 1.0))
+                .unwrap_or(image::Rgba([0, 0, 0, 255]))
+        });
+        image::DynamicImage::ImageRgba8(face)
+    };
+    // face orientations follow the cube map sampling convention of wgpu
+    [
+        face(|s, t| Vector3::new(1.0, -t, -s)),
+        face(|s, t| Vector3::new(-1.0, -t, s)),
+        face(|s, t| Vector3::new(s, 1.0, t)),
+        face(|s, t| Vector3::new(s, -1.0, -t)),
+        face(|s, t| Vector3::new(s, -t, 1.0)),
+        face(|s, t| Vector3::new(-s, -t, -1.0)),
+    ]
+}
@@ -0,0 +1,492 @@
+use rand::Rng;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    render_graph::{RenderGraph, ResourceHandle, TransientTexture},
+    renderer::Msaa,
+    texture::{Texture, TextureFormat, TextureOptions},
+};
+
+pub const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// has to match the kernel size in common.wgsl
+const MAX_SAMPLE_COUNT: u32 = 32;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    kernel: [[f32; 4]; MAX_SAMPLE_COUNT as usize],
+    screen_size: [f32; 2],
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    sample_count: u32,
+    half_resolution: u32,
+    enabled: u32,
+}
+
+/// screen space ambient occlusion computed from the depth prepass, darkening the ambient light of
+/// the main pass
+pub struct Ssao {
+    pub enabled: bool,
+    /// world space radius of the sampled hemisphere
+    pub radius: f32,
+    /// depth difference below which samples don't count as occluded, against self occlusion
+    pub bias: f32,
+    /// exponent applied to the ambient occlusion
+    pub intensity: f32,
+    /// renders the occlusion at half the size of the surface
+    pub half_resolution: bool,
+    sample_count: u32,
+    uniform: SsaoUniform,
+    buffer: wgpu::Buffer,
+    depth_bind_group_layout: wgpu::BindGroupLayout,
+    depth_bind_group: wgpu::BindGroup,
+    source_bind_group_layout: wgpu::BindGroupLayout,
+    lighting_bind_group_layout: wgpu::BindGroupLayout,
+    /// white texture standing in for the occlusion while ssao is disabled. never read, it is only
+    /// held so the texture lives as long as `fallback_lighting_bind_group` binding it.
+    _fallback: Texture,
+    fallback_lighting_bind_group: wgpu::BindGroup,
+    ssao_pipeline: wgpu::RenderPipeline,
+    blur_horizontal_pipeline: wgpu::RenderPipeline,
+    blur_vertical_pipeline: wgpu::RenderPipeline,
+}
+
+impl Ssao {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        msaa: Msaa,
+    ) -> Self {
+        let sample_count = 16;
+        let uniform = SsaoUniform {
+            kernel: create_kernel(sample_count),
+            screen_size: [config.width as f32, config.height as f32],
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.5,
+            sample_count,
+            half_resolution: 1,
+            enabled: 1,
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("ssao_buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let depth_bind_group_layout = Self::create_depth_bind_group_layout(device, msaa);
+        let depth_bind_group =
+            Self::create_depth_bind_group(device, &depth_bind_group_layout, depth_texture, &buffer);
+        let source_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("ssao_source_bind_group_layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            });
+        let lighting_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("ssao_lighting_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let fallback = Texture::from_image(
+            device,
+            queue,
+            &TextureOptions::new()
+                .label("ssao_fallback")
+                .format(TextureFormat::R8),
+            &image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(
+                1,
+                1,
+                image::Luma([255]),
+            )),
+        );
+        let fallback_lighting_bind_group =
+            Self::lighting_bind_group(device, &lighting_bind_group_layout, &fallback, &buffer);
+        let [ssao_pipeline, blur_horizontal_pipeline, blur_vertical_pipeline] =
+            Self::create_pipelines(
+                device,
+                camera_layout,
+                &depth_bind_group_layout,
+                &source_bind_group_layout,
+                msaa,
+            );
+
+        Self {
+            enabled: true,
+            radius: uniform.radius,
+            bias: uniform.bias,
+            intensity: uniform.intensity,
+            sample_count,
+            half_resolution: true,
+            uniform,
+            buffer,
+            depth_bind_group_layout,
+            depth_bind_group,
+            source_bind_group_layout,
+            lighting_bind_group_layout,
+            _fallback: fallback,
+            fallback_lighting_bind_group,
+            ssao_pipeline,
+            blur_horizontal_pipeline,
+            blur_vertical_pipeline,
+        }
+    }
+
+    /// occlusion texture, sampler and parameters, bound by the main pass
+    pub fn lighting_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.lighting_bind_group_layout
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// clamped to 1..=32
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        self.sample_count = sample_count.clamp(1, MAX_SAMPLE_COUNT);
+        self.uniform.kernel = create_kernel(self.sample_count);
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_texture: &Texture,
+    ) {
+        self.uniform.screen_size = [config.width as f32, config.height as f32];
+        self.depth_bind_group = Self::create_depth_bind_group(
+            device,
+            &self.depth_bind_group_layout,
+            depth_texture,
+            &self.buffer,
+        );
+    }
+
+    /// has to be called whenever the depth texture is recreated with a different sample count
+    pub fn set_msaa(
+        &mut self,
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        msaa: Msaa,
+    ) {
+        self.depth_bind_group_layout = Self::create_depth_bind_group_layout(device, msaa);
+        self.depth_bind_group = Self::create_depth_bind_group(
+            device,
+            &self.depth_bind_group_layout,
+            depth_texture,
+            &self.buffer,
+        );
+        [
+            self.ssao_pipeline,
+            self.blur_horizontal_pipeline,
+            self.blur_vertical_pipeline,
+        ] = Self::create_pipelines(
+            device,
+            camera_layout,
+            &self.depth_bind_group_layout,
+            &self.source_bind_group_layout,
+            msaa,
+        );
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        self.uniform.radius = self.radius;
+        self.uniform.bias = self.bias;
+        self.uniform.intensity = self.intensity;
+        self.uniform.sample_count = self.sample_count;
+        self.uniform.half_resolution = self.half_resolution as u32;
+        self.uniform.enabled = self.enabled as u32;
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    /// adds the ssao pass and the blur passes reading `depth`, which has to be written by a depth
+    /// prepass first. returns the blurred occlusion.
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        depth: ResourceHandle,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) -> ResourceHandle {
+        let divisor = if self.half_resolution { 2 } else { 1 };
+        let mut occlusion = None;
+        let passes = [
+            ("ssao_pass", &self.ssao_pipeline),
+            ("ssao_blur_horizontal_pass", &self.blur_horizontal_pipeline),
+            ("ssao_blur_vertical_pass", &self.blur_vertical_pipeline),
+        ];
+        for (label, pipeline) in passes {
+            let target = graph
+                .create_texture(TransientTexture::new("ssao", AO_FORMAT).size_divisor(divisor));
+            let mut pass = graph.add_pass(label);
+            pass.read(depth);
+            let source = occlusion.map(|source| pass.read(source));
+            let target = pass.write(target);
+            pass.execute(move |context| {
+                let source_bind_group = source.map(|source| {
+                    context
+                        .device
+                        .create_bind_group(&wgpu::BindGroupDescriptor {
+                            label: Some("ssao_source_bind_group"),
+                            layout: &self.source_bind_group_layout,
+                            entries: &[wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: wgpu::BindingResource::TextureView(
+                                    context.resources.view(source),
+                                ),
+                            }],
+                        })
+                });
+                let mut render_pass =
+                    context
+                        .encoder
+                        .begin_render_pass(&wgpu::RenderPassDescriptor {
+                            label: Some(label),
+                            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                                view: context.resources.view(target),
+                                resolve_target: None,
+                                ops: wgpu::Operations {
+                                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                                    store: true,
+                                },
+                            })],
+                            depth_stencil_attachment: None,
+                        });
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, camera_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.depth_bind_group, &[]);
+                if let Some(source_bind_group) = &source_bind_group {
+                    render_pass.set_bind_group(2, source_bind_group, &[]);
+                }
+                render_pass.draw(0..3, 0..1);
+            });
+            occlusion = Some(target);
+        }
+        occlusion.expect("ssao adds at least one pass")
+    }
+
+    /// binds the occlusion returned by `add_passes` for the main pass
+    pub fn create_lighting_bind_group(
+        &self,
+        device: &wgpu::Device,
+        occlusion: &Texture,
+    ) -> wgpu::BindGroup {
+        Self::lighting_bind_group(
+            device,
+            &self.lighting_bind_group_layout,
+            occlusion,
+            &self.buffer,
+        )
+    }
+
+    /// bound by the main pass while ssao is disabled
+    pub fn fallback_lighting_bind_group(&self) -> &wgpu::BindGroup {
+        &self.fallback_lighting_bind_group
+    }
+
+    fn create_depth_bind_group_layout(device: &wgpu::Device, msaa: Msaa) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("ssao_depth_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: msaa != Msaa::Off,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn create_depth_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ssao_depth_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn lighting_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        occlusion: &Texture,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ssao_lighting_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&occlusion.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&occlusion.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// the ssao pass and the horizontal and vertical blur passes
+    fn create_pipelines(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        depth_layout: &wgpu::BindGroupLayout,
+        source_layout: &wgpu::BindGroupLayout,
+        msaa: Msaa,
+    ) -> [wgpu::RenderPipeline; 3] {
+        let depth_texture_type = if msaa == Msaa::Off {
+            "texture_depth_2d"
+        } else {
+            "texture_depth_multisampled_2d"
+        };
+        let source = format!(
+            "type DepthTexture = {};\n{}{}",
+            depth_texture_type,
+            include_str!("./shaders/common.wgsl"),
+            include_str!("./shaders/ssao.wgsl")
+        );
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("ssao_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(source)),
+        });
+        let ssao_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ssao_pipeline_layout"),
+            bind_group_layouts: &[camera_layout, depth_layout],
+            push_constant_ranges: &[],
+        });
+        let blur_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ssao_blur_pipeline_layout"),
+            bind_group_layouts: &[camera_layout, depth_layout, source_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |layout, entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    targets: &[Some(AO_FORMAT.into())],
+                }),
+                multiview: None,
+            })
+        };
+
+        [
+            create_pipeline(&ssao_layout, "fs_ssao"),
+            create_pipeline(&blur_layout, "fs_blur_horizontal"),
+            create_pipeline(&blur_layout, "fs_blur_vertical"),
+        ]
+    }
+}
+
+/// random samples in the +z hemisphere, packed closer to the origin so nearby occluders weigh
+/// more
+fn create_kernel(sample_count: u32) -> [[f32; 4]; MAX_SAMPLE_COUNT as usize] {
+    let mut rng = rand::thread_rng();
+    let mut kernel = [[0.0; 4]; MAX_SAMPLE_COUNT as usize];
+    for (i, sample) in kernel.iter_mut().take(sample_count as usize).enumerate() {
+        let direction = cgmath::Vector3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(0.0..1.0),
+        );
+        let direction = if direction == cgmath::Vector3::new(0.0, 0.0, 0.0) {
+            cgmath::Vector3::unit_z()
+        } else {
+            cgmath::InnerSpace::normalize(direction)
+        };
+        let t = i as f32 / sample_count as f32;
+        let scale = 0.1 + 0.9 * t * t;
+        let length = rng.gen_range(0.0..1.0) * scale;
+        *sample = [
+            direction.x * length,
+            direction.y * length,
+            direction.z * length,
+            0.0,
+        ];
+    }
+    kernel
+}
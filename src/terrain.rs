@@ -0,0 +1,123 @@
+use cgmath::{InnerSpace, Vector3};
+
+/// heights on a regular grid in the xz plane, sampled bilinearly in between. positions outside
+/// the grid take the height of the nearest edge.
+#[derive(Debug, Clone)]
+pub struct HeightField {
+    /// world position of the first sample
+    origin: (f32, f32),
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    /// row major, x runs along a row
+    heights: Vec<f32>,
+}
+
+impl HeightField {
+    /// a single cell at `height` spanning `size` around the origin
+    pub fn flat(size: f32, height: f32) -> Self {
+        Self {
+            origin: (-size / 2.0, -size / 2.0),
+            cell_size: size,
+            columns: 2,
+            rows: 2,
+            heights: vec![height; 4],
+        }
+    }
+
+    /// samples `height` at every grid point, `columns` and `rows` have to be at least two
+    pub fn from_fn(
+        origin: (f32, f32),
+        cell_size: f32,
+        columns: usize,
+        rows: usize,
+        height: impl Fn(f32, f32) -> f32,
+    ) -> Self {
+        assert!(
+            columns >= 2 && rows >= 2,
+            "a height field needs at least 2x2 samples"
+        );
+        let mut heights = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                heights.push(height(
+                    origin.0 + column as f32 * cell_size,
+                    origin.1 + row as f32 * cell_size,
+                ))
+            }
+        }
+        Self {
+            origin,
+            cell_size,
+            columns,
+            rows,
+            heights,
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// the covered area as minimum and maximum corners in the xz plane
+    pub fn bounds(&self) -> ((f32, f32), (f32, f32)) {
+        (
+            self.origin,
+            (
+                self.origin.0 + (self.columns - 1) as f32 * self.cell_size,
+                self.origin.1 + (self.rows - 1) as f32 * self.cell_size,
+            ),
+        )
+    }
+
+    /// lowest and highest sample
+    pub fn height_range(&self) -> (f32, f32) {
+        self.heights
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), height| {
+                (min.min(*height), max.max(*height))
+            })
+    }
+
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let x = ((x - self.origin.0) / self.cell_size).clamp(0.0, (self.columns - 1) as f32);
+        let z = ((z - self.origin.1) / self.cell_size).clamp(0.0, (self.rows - 1) as f32);
+        let column = (x.floor() as usize).min(self.columns - 2);
+        let row = (z.floor() as usize).min(self.rows - 2);
+        let (fx, fz) = (x - column as f32, z - row as f32);
+
+        let sample = |column: usize, row: usize| self.heights[row * self.columns + column];
+        let top = sample(column, row) * (1.0 - fx) + sample(column + 1, row) * fx;
+        let bottom = sample(column, row + 1) * (1.0 - fx) + sample(column + 1, row + 1) * fx;
+        top * (1.0 - fz) + bottom * fz
+    }
+
+    /// surface normal from central differences
+    pub fn normal_at(&self, x: f32, z: f32) -> Vector3<f32> {
+        let step = self.cell_size * 0.5;
+        let dx = self.height_at(x + step, z) - self.height_at(x - step, z);
+        let dz = self.height_at(x, z + step) - self.height_at(x, z - step);
+        Vector3::new(-dx, 2.0 * step, -dz).normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heights_are_bilinear_and_clamped() {
+        let field = HeightField::from_fn((0.0, 0.0), 1.0, 3, 3, |x, z| x + 2.0 * z);
+        assert_eq!(field.height_at(0.5, 0.5), 1.5);
+        assert_eq!(field.height_at(2.0, 2.0), 6.0);
+        assert_eq!(field.height_at(10.0, -10.0), 2.0);
+        assert_eq!(field.height_range(), (0.0, 6.0));
+    }
+
+    #[test]
+    fn flat_field_points_up() {
+        let field = HeightField::flat(100.0, -1.0);
+        assert_eq!(field.height_at(12.0, -30.0), -1.0);
+        assert_eq!(field.normal_at(0.0, 0.0), Vector3::unit_y());
+    }
+}
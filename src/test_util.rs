@@ -0,0 +1,15 @@
+use std::time::Duration;
+
+use cgmath::{InnerSpace, Vector3};
+
+pub fn ms(milliseconds: u64) -> Duration {
+    Duration::from_millis(milliseconds)
+}
+
+pub fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 1e-4, "{} != {}", a, b)
+}
+
+pub fn assert_close_vector(a: Vector3<f32>, b: Vector3<f32>) {
+    assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b)
+}
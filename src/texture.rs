@@ -1,40 +1,305 @@
+use anyhow::anyhow;
+use image::GenericImageView;
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+    /// color data like albedo textures
+    Rgba8Srgb,
+    /// non-color data like normal maps, which must not be gamma corrected when sampled
+    Rgba8Linear,
+    /// single channel masks, taken from the red channel of the image
+    R8,
+    /// dual channel masks, taken from the red and green channels of the image
+    Rg8,
+}
+
+impl TextureFormat {
+    pub fn wgpu_format(&self) -> wgpu::TextureFormat {
+        match self {
+            TextureFormat::Rgba8Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Rgba8Linear => wgpu::TextureFormat::Rgba8Unorm,
+            TextureFormat::R8 => wgpu::TextureFormat::R8Unorm,
+            TextureFormat::Rg8 => wgpu::TextureFormat::Rg8Unorm,
+        }
+    }
+
+    pub fn bytes_per_pixel(&self) -> u32 {
+        match self {
+            TextureFormat::Rgba8Srgb | TextureFormat::Rgba8Linear => 4,
+            TextureFormat::R8 => 1,
+            TextureFormat::Rg8 => 2,
+        }
+    }
+
+    fn image_bytes(&self, image: &image::DynamicImage) -> Vec<u8> {
+        let rgba = image.to_rgba8();
+        let channels = self.bytes_per_pixel() as usize;
+        if channels == 4 {
+            rgba.into_raw()
+        } else {
+            rgba.pixels()
+                .flat_map(|pixel| pixel.0.into_iter().take(channels))
+                .collect()
+        }
+    }
+}
+
+/// describes how a texture is created and sampled
+#[derive(Debug, Clone)]
+pub struct TextureOptions {
+    label: Option<String>,
+    format: TextureFormat,
+    address_mode_u: wgpu::AddressMode,
+    address_mode_v: wgpu::AddressMode,
+    mag_filter: wgpu::FilterMode,
+    min_filter: wgpu::FilterMode,
+    mipmap_filter: wgpu::FilterMode,
+    anisotropy: Option<std::num::NonZeroU8>,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            label: None,
+            format: TextureFormat::Rgba8Srgb,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: None,
+        }
+    }
+}
+
+impl TextureOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn format(mut self, format: TextureFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// sets the address mode for both the u and v coordinate
+    pub fn address_mode(self, address_mode: wgpu::AddressMode) -> Self {
+        self.address_modes(address_mode, address_mode)
+    }
+
+    pub fn address_modes(mut self, u: wgpu::AddressMode, v: wgpu::AddressMode) -> Self {
+        self.address_mode_u = u;
+        self.address_mode_v = v;
+        self
+    }
+
+    /// sets the mag, min and mipmap filter at once
+    pub fn filter(self, filter: wgpu::FilterMode) -> Self {
+        self.mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_filter(filter)
+    }
+
+    pub fn mag_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.mag_filter = filter;
+        self
+    }
+
+    pub fn min_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.min_filter = filter;
+        self
+    }
+
+    pub fn mipmap_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.mipmap_filter = filter;
+        self
+    }
+
+    /// has to be a power of two up to 16 and requires all filters to be linear
+    pub fn anisotropy(mut self, anisotropy: u8) -> Self {
+        self.anisotropy = std::num::NonZeroU8::new(anisotropy);
+        self
+    }
+
+    fn texture_label(&self) -> Option<&str> {
+        Some(self.label.as_deref().unwrap_or("texture"))
+    }
+
+    fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        let label = self
+            .label
+            .as_ref()
+            .map(|label| format!("{}_sampler", label));
+        device
+            .create_sampler(&self.sampler_descriptor(label.as_deref().unwrap_or("texture_sampler")))
+    }
+
+    fn sampler_descriptor<'a>(&self, label: &'a str) -> wgpu::SamplerDescriptor<'a> {
+        wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: self.anisotropy,
+            ..Default::default()
+        }
+    }
+}
+
+fn check_array_layers(images: &[image::DynamicImage]) -> anyhow::Result<()> {
+    let first = images
+        .first()
+        .ok_or_else(|| anyhow!("cannot create texture array without images"))?;
+    if let Some(image) = images
+        .iter()
+        .find(|image| image.dimensions() != first.dimensions())
+    {
+        return Err(anyhow!(
+            "all images of a texture array must be of equal size, expected {:?} but got {:?}",
+            first.dimensions(),
+            image.dimensions()
+        ));
+    }
+    Ok(())
+}
+
+/// decodes an encoded image like a png or jpeg, the format is guessed from the bytes
+fn decode(bytes: &[u8]) -> anyhow::Result<image::DynamicImage> {
+    image::load_from_memory(bytes).map_err(|err| anyhow!("failed to decode texture: {}", err))
+}
+
 impl Texture {
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        filter_mode: wgpu::FilterMode,
+        options: &TextureOptions,
         bytes: &[u8],
     ) -> anyhow::Result<Self> {
-        let image = image::load_from_memory(bytes)?;
-        Ok(Self::from_image(device, queue, filter_mode, &image))
+        Ok(Self::from_image(device, queue, options, &decode(bytes)?))
     }
 
+    /// uploads the image together with a full mip chain. the mip levels are generated on the gpu
+    /// if the format can be rendered to and on the cpu otherwise.
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        filter_mode: wgpu::FilterMode,
+        options: &TextureOptions,
         image: &image::DynamicImage,
     ) -> Self {
-        let rgba = image.to_rgba8();
-        let dimensions = rgba.dimensions();
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+        Self::from_layers(
+            device,
+            queue,
+            options,
+            std::slice::from_ref(image),
+            wgpu::TextureViewDimension::D2,
+        )
+    }
+
+    /// creates a `D2Array` texture with one layer per image, so variants can be selected by a
+    /// layer index instead of switching bind groups
+    pub fn from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        options: &TextureOptions,
+        images: &[image::DynamicImage],
+    ) -> anyhow::Result<Self> {
+        check_array_layers(images)?;
+        Ok(Self::from_layers(
+            device,
+            queue,
+            options,
+            images,
+            wgpu::TextureViewDimension::D2Array,
+        ))
+    }
+
+    /// creates a cube texture from faces in the order +x, -x, +y, -y, +z, -z
+    pub fn from_cube_faces(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        options: &TextureOptions,
+        faces: &[image::DynamicImage; 6],
+    ) -> anyhow::Result<Self> {
+        let (width, height) = faces[0].dimensions();
+        if width != height {
+            return Err(anyhow!(
+                "cube faces have to be square, got {}x{}",
+                width,
+                height
+            ));
+        }
+        if faces
+            .iter()
+            .any(|face| face.dimensions() != (width, height))
+        {
+            return Err(anyhow!("all cube faces must be of equal size"));
+        }
+        Ok(Self::from_layers(
+            device,
+            queue,
+            options,
+            faces,
+            wgpu::TextureViewDimension::Cube,
+        ))
+    }
+
+    /// creates a 3d color lookup table from a strip of `size` slices of `size`x`size` texels laid
+    /// out horizontally, red increasing along x, green along y and blue from slice to slice
+    pub fn from_lut(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        options: &TextureOptions,
+        image: &image::DynamicImage,
+    ) -> anyhow::Result<Self> {
+        let (width, size) = image.dimensions();
+        if width != size * size {
+            return Err(anyhow!(
+                "a lut of size {} has to be {}x{}, got {}x{}",
+                size,
+                size * size,
+                size,
+                width,
+                size
+            ));
+        }
+        let bytes = options.format.image_bytes(image);
+        let bytes_per_pixel = options.format.bytes_per_pixel() as usize;
+        let row_size = (size as usize) * bytes_per_pixel;
+        // reorder the slices from side by side in the strip to one after another in depth
+        let mut data = Vec::with_capacity(bytes.len());
+        for slice in 0..size as usize {
+            for y in 0..size as usize {
+                let start = (y * width as usize + slice * size as usize) * bytes_per_pixel;
+                data.extend_from_slice(&bytes[start..start + row_size]);
+            }
+        }
+
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: size,
         };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("texture"),
-            size,
+            label: options.texture_label(),
+            size: extent,
             mip_level_count: 1,
             sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            dimension: wgpu::TextureDimension::D3,
+            format: options.format.wgpu_format(),
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
@@ -45,25 +310,87 @@ impl Texture {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            &rgba,
+            &data,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
+                bytes_per_row: std::num::NonZeroU32::new(row_size as u32),
+                rows_per_image: std::num::NonZeroU32::new(size),
             },
-            size,
+            extent,
         );
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("texture_sampler"),
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: filter_mode,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+        let sampler = options.create_sampler(device);
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// expects at least one image and all images to be of equal size
+    fn from_layers(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        options: &TextureOptions,
+        layers: &[image::DynamicImage],
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Self {
+        let (width, height) = layers[0].dimensions();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers.len() as u32,
+        };
+        let format = options.format.wgpu_format();
+        let mip_level_count = mip_level_count(width, height);
+        let gpu_mipmaps = format
+            .describe()
+            .guaranteed_format_features
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT);
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if gpu_mipmaps {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: options.texture_label(),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+        for (layer, image) in layers.iter().enumerate() {
+            write_mip_level(queue, &texture, options.format, 0, layer as u32, image);
+            if !gpu_mipmaps {
+                generate_mipmaps_cpu(
+                    queue,
+                    &texture,
+                    options.format,
+                    image,
+                    layer as u32,
+                    mip_level_count,
+                );
+            }
+        }
+        if gpu_mipmaps {
+            generate_mipmaps_gpu(
+                device,
+                queue,
+                &texture,
+                format,
+                layers.len() as u32,
+                mip_level_count,
+            );
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(view_dimension),
             ..Default::default()
         });
+        let sampler = options.create_sampler(device);
 
         Self {
             texture,
@@ -72,9 +399,12 @@ impl Texture {
         }
     }
 
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
     ) -> Self {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("depth_texture"),
@@ -84,9 +414,9 @@ impl Texture {
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
+            format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
         });
@@ -110,4 +440,542 @@ impl Texture {
             sampler,
         }
     }
+
+    /// color target which can be bound as a texture by following passes. the view covers all mip
+    /// levels.
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        mip_level_count: u32,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&format!("{}_sampler", label)),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+}
+
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+fn write_mip_level(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: TextureFormat,
+    mip_level: u32,
+    layer: u32,
+    image: &image::DynamicImage,
+) {
+    let (width, height) = (image.width(), image.height());
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: layer,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        &format.image_bytes(image),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(format.bytes_per_pixel() * width),
+            rows_per_image: std::num::NonZeroU32::new(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+fn generate_mipmaps_cpu(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: TextureFormat,
+    image: &image::DynamicImage,
+    layer: u32,
+    mip_level_count: u32,
+) {
+    let mut previous = image.clone();
+    for mip_level in 1..mip_level_count {
+        let width = (image.width() >> mip_level).max(1);
+        let height = (image.height() >> mip_level).max(1);
+        let level = previous.resize_exact(width, height, image::imageops::FilterType::Triangle);
+        write_mip_level(queue, texture, format, mip_level, layer, &level);
+        previous = level;
+    }
+}
+
+/// renders every mip level by sampling the previous one with a linear filter
+fn generate_mipmaps_gpu(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    layer_count: u32,
+    mip_level_count: u32,
+) {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("blit_shader_module"),
+        source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(include_str!(
+            "./shaders/blit.wgsl"
+        ))),
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("mipmap_pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        multiview: None,
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("mipmap_sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("mipmap_command_encoder"),
+    });
+    for layer in 0..layer_count {
+        let views = (0..mip_level_count)
+            .map(|mip_level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("mipmap_view"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_mip_level: mip_level,
+                    mip_level_count: std::num::NonZeroU32::new(1),
+                    base_array_layer: layer,
+                    array_layer_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for target_mip in 1..mip_level_count as usize {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("mipmap_bind_group"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&views[target_mip - 1]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            });
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("mipmap_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &views[target_mip],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+    queue.submit(std::iter::once(command_encoder.finish()));
+}
+
+/// location of an image inside a texture atlas in uv space, laid out to be used directly as
+/// per-instance data: `uv = offset + uv * size`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct AtlasRect {
+    pub offset: [f32; 2],
+    pub size: [f32; 2],
+}
+
+pub struct TextureAtlas {
+    pub texture: Texture,
+    /// in the order the images were added to the builder
+    pub rects: Vec<AtlasRect>,
+}
+
+struct AtlasLayout {
+    width: u32,
+    height: u32,
+    /// top left corners of the padded images
+    positions: Vec<(u32, u32)>,
+    rects: Vec<AtlasRect>,
+}
+
+/// packs images of different sizes into a single texture using shelf packing
+pub struct TextureAtlasBuilder {
+    images: Vec<image::RgbaImage>,
+    padding: u32,
+    max_size: u32,
+}
+
+impl TextureAtlasBuilder {
+    pub fn new() -> Self {
+        Self {
+            images: vec![],
+            padding: 2,
+            max_size: wgpu::Limits::default().max_texture_dimension_2d,
+        }
+    }
+
+    /// the padding around each image is filled with its edge pixels, so filtering doesn't bleed
+    /// neighbouring images into each other. every mip level halves it, so only the first
+    /// log2(padding) + 1 levels are protected, lower ones still bleed.
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// returns the index of the image's rect in the built atlas
+    pub fn add_image(&mut self, image: &image::DynamicImage) -> usize {
+        self.images.push(image.to_rgba8());
+        self.images.len() - 1
+    }
+
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        options: &TextureOptions,
+    ) -> anyhow::Result<TextureAtlas> {
+        let layout = self.pack()?;
+        let texture = Texture::from_image(
+            device,
+            queue,
+            options,
+            &image::DynamicImage::ImageRgba8(self.compose(&layout)),
+        );
+        Ok(TextureAtlas {
+            texture,
+            rects: layout.rects,
+        })
+    }
+
+    /// copies the images to their packed positions, surrounded by their edge pixels
+    fn compose(&self, layout: &AtlasLayout) -> image::RgbaImage {
+        let mut atlas = image::RgbaImage::new(layout.width, layout.height);
+        let padding = self.padding as i64;
+        for (image, &(x, y)) in self.images.iter().zip(layout.positions.iter()) {
+            let (image_width, image_height) = image.dimensions();
+            for py in 0..image_height as i64 + 2 * padding {
+                for px in 0..image_width as i64 + 2 * padding {
+                    let source_x = (px - padding).clamp(0, image_width as i64 - 1) as u32;
+                    let source_y = (py - padding).clamp(0, image_height as i64 - 1) as u32;
+                    atlas.put_pixel(
+                        x + px as u32,
+                        y + py as u32,
+                        *image.get_pixel(source_x, source_y),
+                    );
+                }
+            }
+        }
+        atlas
+    }
+
+    /// tries square atlas widths in powers of two until every image fits
+    fn pack(&self) -> anyhow::Result<AtlasLayout> {
+        if self.images.is_empty() {
+            return Err(anyhow!("cannot build texture atlas without images"));
+        }
+        let padded = |image: &image::RgbaImage| {
+            (
+                image.width() + 2 * self.padding,
+                image.height() + 2 * self.padding,
+            )
+        };
+        let mut order = (0..self.images.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| std::cmp::Reverse(padded(&self.images[index]).1));
+
+        let area: u64 = self
+            .images
+            .iter()
+            .map(|image| {
+                let (width, height) = padded(image);
+                width as u64 * height as u64
+            })
+            .sum();
+        let widest = self
+            .images
+            .iter()
+            .map(|image| padded(image).0)
+            .max()
+            .unwrap_or(1);
+        let mut width = ((area as f64).sqrt().ceil() as u32)
+            .max(widest)
+            .next_power_of_two();
+
+        while width <= self.max_size {
+            let mut positions = vec![(0, 0); self.images.len()];
+            let (mut shelf_x, mut shelf_y, mut shelf_height) = (0, 0, 0);
+            for &index in &order {
+                let (image_width, image_height) = padded(&self.images[index]);
+                if shelf_x + image_width > width {
+                    shelf_y += shelf_height;
+                    shelf_x = 0;
+                    shelf_height = 0;
+                }
+                positions[index] = (shelf_x, shelf_y);
+                shelf_x += image_width;
+                shelf_height = shelf_height.max(image_height);
+            }
+            let height = shelf_y + shelf_height;
+            if height <= width {
+                let rects = self
+                    .images
+                    .iter()
+                    .zip(positions.iter())
+                    .map(|(image, &(x, y))| AtlasRect {
+                        offset: [
+                            (x + self.padding) as f32 / width as f32,
+                            (y + self.padding) as f32 / height as f32,
+                        ],
+                        size: [
+                            image.width() as f32 / width as f32,
+                            image.height() as f32 / height as f32,
+                        ],
+                    })
+                    .collect();
+                return Ok(AtlasLayout {
+                    width,
+                    height,
+                    positions,
+                    rects,
+                });
+            }
+            width *= 2;
+        }
+
+        Err(anyhow!(
+            "images do not fit into a texture atlas of at most {0}x{0}",
+            self.max_size
+        ))
+    }
+}
+
+impl Default for TextureAtlasBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: u8) -> image::DynamicImage {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            width,
+            height,
+            image::Rgba([value; 4]),
+        ))
+    }
+
+    fn builder(sizes: &[(u32, u32)]) -> TextureAtlasBuilder {
+        let mut builder = TextureAtlasBuilder::new();
+        for (index, &(width, height)) in sizes.iter().enumerate() {
+            builder.add_image(&solid(width, height, index as u8 + 1));
+        }
+        builder
+    }
+
+    #[test]
+    fn address_modes_apply_to_u_and_v() {
+        let descriptor = TextureOptions::new()
+            .address_mode(wgpu::AddressMode::Repeat)
+            .sampler_descriptor("sampler");
+        assert_eq!(descriptor.address_mode_u, wgpu::AddressMode::Repeat);
+        assert_eq!(descriptor.address_mode_v, wgpu::AddressMode::Repeat);
+        assert_eq!(descriptor.address_mode_w, wgpu::AddressMode::ClampToEdge);
+
+        let descriptor = TextureOptions::new()
+            .address_modes(wgpu::AddressMode::MirrorRepeat, wgpu::AddressMode::Repeat)
+            .sampler_descriptor("sampler");
+        assert_eq!(descriptor.address_mode_u, wgpu::AddressMode::MirrorRepeat);
+        assert_eq!(descriptor.address_mode_v, wgpu::AddressMode::Repeat);
+    }
+
+    #[test]
+    fn decoded_bytes_keep_the_channels_of_the_format() {
+        let image = image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([x as u8, 10, 20, 30]));
+        let mut bytes = std::io::Cursor::new(vec![]);
+        image::DynamicImage::ImageRgba8(image)
+            .write_to(&mut bytes, image::ImageOutputFormat::Png)
+            .unwrap();
+        let image = decode(bytes.get_ref()).unwrap();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(TextureFormat::Rg8.bytes_per_pixel(), 2);
+        assert_eq!(TextureFormat::Rg8.image_bytes(&image), [0, 10, 1, 10]);
+        assert_eq!(TextureFormat::R8.image_bytes(&image), [0, 1]);
+    }
+
+    #[test]
+    fn undecodable_bytes_are_an_error() {
+        assert!(decode(b"not an image").is_err());
+    }
+
+    #[test]
+    fn array_layers_have_to_be_of_equal_size() {
+        assert!(check_array_layers(&[]).is_err());
+        assert!(check_array_layers(&[solid(4, 4, 0), solid(4, 4, 1)]).is_ok());
+        assert!(check_array_layers(&[solid(4, 4, 0), solid(4, 2, 1)]).is_err());
+    }
+
+    #[test]
+    fn packed_images_do_not_overlap() {
+        let builder = builder(&[(30, 10), (12, 40), (25, 25), (8, 8), (50, 3)]);
+        let layout = builder.pack().unwrap();
+        assert!(layout.width.is_power_of_two());
+        assert!(layout.height <= layout.width);
+
+        let padded = |index: usize| {
+            let (x, y) = layout.positions[index];
+            let (width, height) = builder.images[index].dimensions();
+            (
+                x,
+                y,
+                x + width + 2 * builder.padding,
+                y + height + 2 * builder.padding,
+            )
+        };
+        for a in 0..builder.images.len() {
+            let (left, top, right, bottom) = padded(a);
+            assert!(right <= layout.width && bottom <= layout.height);
+            for b in a + 1..builder.images.len() {
+                let (other_left, other_top, other_right, other_bottom) = padded(b);
+                assert!(
+                    right <= other_left
+                        || other_right <= left
+                        || bottom <= other_top
+                        || other_bottom <= top,
+                    "images {} and {} overlap",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn padding_repeats_the_edge_pixels() {
+        let mut builder = TextureAtlasBuilder::new().padding(3);
+        let image = image::RgbaImage::from_fn(4, 4, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+        builder.add_image(&image::DynamicImage::ImageRgba8(image.clone()));
+        let layout = builder.pack().unwrap();
+        let atlas = builder.compose(&layout);
+
+        let (x, y) = layout.positions[0];
+        for py in 0..10 {
+            for px in 0..10 {
+                let source = image.get_pixel(
+                    (px as i32 - 3).clamp(0, 3) as u32,
+                    (py as i32 - 3).clamp(0, 3) as u32,
+                );
+                assert_eq!(atlas.get_pixel(x + px, y + py), source);
+            }
+        }
+    }
+
+    #[test]
+    fn rects_cover_the_images_without_padding() {
+        let builder = builder(&[(20, 10), (6, 6)]).padding(1);
+        let layout = builder.pack().unwrap();
+        let atlas = builder.compose(&layout);
+
+        for (index, rect) in layout.rects.iter().enumerate() {
+            let (image_width, image_height) = builder.images[index].dimensions();
+            let (x, y) = layout.positions[index];
+            assert_eq!(rect.offset[0] * layout.width as f32, (x + 1) as f32);
+            assert_eq!(rect.offset[1] * layout.height as f32, (y + 1) as f32);
+            assert_eq!(rect.size[0] * layout.width as f32, image_width as f32);
+            assert_eq!(rect.size[1] * layout.height as f32, image_height as f32);
+
+            let (left, top) = (x + 1, y + 1);
+            for (px, py) in [
+                (left, top),
+                (left + image_width - 1, top + image_height - 1),
+            ] {
+                assert_eq!(atlas.get_pixel(px, py).0, [index as u8 + 1; 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn pack_grows_until_the_images_fit() {
+        let layout = builder(&[(60, 60); 4]).padding(0).pack().unwrap();
+        assert_eq!(layout.width, 128);
+
+        let layout = builder(&[(60, 60); 5]).padding(0).pack().unwrap();
+        assert_eq!(layout.width, 256);
+    }
+
+    #[test]
+    fn pack_fails_beyond_the_max_size() {
+        assert!(builder(&[(60, 60); 5])
+            .padding(0)
+            .max_size(128)
+            .pack()
+            .is_err());
+        assert!(builder(&[(100, 10)])
+            .padding(20)
+            .max_size(128)
+            .pack()
+            .is_err());
+        assert!(TextureAtlasBuilder::new().pack().is_err());
+    }
 }
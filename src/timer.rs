@@ -1,11 +1,21 @@
 use std::time::Duration;
 
+/// counts down while started. it has no notion of time on its own and is advanced by whoever
+/// owns it, usually with `Timing::time_delta`, so it follows the time scale and pause of the
+/// game clock, or with `Timing::real_time_delta` to ignore them.
+///
+/// a timer is created stopped and only moves once started. a one-shot timer stays finished
+/// until it is reset or stopped, a looping timer wraps around and carries the overshoot into
+/// the next loop, `finished` is then only true for the update it wrapped in.
+#[derive(Debug, Clone)]
 pub struct Timer {
     duration: Duration,
     remaining: Duration,
     started: bool,
+    paused: bool,
     looping: bool,
     finished: bool,
+    times_finished: u32,
 }
 
 impl Timer {
@@ -14,46 +24,112 @@ impl Timer {
             duration,
             remaining: duration,
             started: false,
+            paused: false,
             looping,
             finished: false,
+            times_finished: 0,
         }
     }
 
-    pub fn update(&mut self, time_delta: Duration) {
-        if self.finished() && self.looping {
-            self.reset()
+    /// returns how often the timer finished during this update, which can be more than once
+    /// for a looping timer shorter than `time_delta`
+    pub fn update(&mut self, time_delta: Duration) -> u32 {
+        self.times_finished = 0;
+        if self.looping {
+            self.finished = false
+        }
+        if !self.running() || self.finished {
+            return 0;
+        }
+
+        if time_delta < self.remaining {
+            self.remaining -= time_delta;
+            return 0;
         }
 
-        if self.remaining < time_delta {
-            self.remaining = Duration::from_secs(0);
-            self.finished = true
-        } else if self.started {
-            self.remaining -= time_delta
+        self.finished = true;
+        if !self.looping || self.duration.is_zero() {
+            // a zero length loop can't wrap more than once without spinning forever
+            self.remaining = if self.looping {
+                self.duration
+            } else {
+                Duration::ZERO
+            };
+            self.times_finished = 1;
+            return 1;
         }
+
+        let overshoot = (time_delta - self.remaining).as_nanos();
+        let duration = self.duration.as_nanos();
+        let wraps = 1 + overshoot / duration;
+        let into_loop = (overshoot % duration) as u64;
+        self.remaining = self.duration - Duration::from_nanos(into_loop);
+        self.times_finished = wraps.min(u32::MAX as u128) as u32;
+        self.times_finished
     }
 
+    /// rewinds to the full duration, the timer keeps running if it was
     pub fn reset(&mut self) {
         self.remaining = self.duration;
         self.finished = false;
-        self.started = self.looping
+        self.times_finished = 0
     }
 
     pub fn start(&mut self) {
-        self.started = true
+        self.started = true;
+        self.paused = false
     }
 
     pub fn started(&self) -> bool {
         self.started
     }
 
+    /// stops and rewinds, unlike `pause`
     pub fn stop(&mut self) {
-        self.started = false
+        self.started = false;
+        self.paused = false;
+        self.reset()
+    }
+
+    /// keeps the progress until `resume`, has no effect on a stopped timer
+    pub fn pause(&mut self) {
+        self.paused = self.started
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// started and not paused
+    pub fn running(&self) -> bool {
+        self.started && !self.paused
+    }
+
+    pub fn looping(&self) -> bool {
+        self.looping
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping
     }
 
     pub fn finished(&self) -> bool {
         self.finished
     }
 
+    /// how often the timer finished during the last update
+    pub fn times_finished(&self) -> u32 {
+        self.times_finished
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
     pub fn elapsed(&self) -> Duration {
         self.duration - self.remaining
     }
@@ -61,4 +137,420 @@ impl Timer {
     pub fn remaining(&self) -> Duration {
         self.remaining
     }
+
+    /// elapsed fraction of the duration in [0, 1]. a zero length timer counts as complete once
+    /// it finished.
+    pub fn progress(&self) -> f32 {
+        if self.duration.is_zero() {
+            return if self.finished { 1.0 } else { 0.0 };
+        }
+        self.elapsed().as_secs_f32() / self.duration.as_secs_f32()
+    }
+}
+
+/// identifies a timer of a `TimerScheduler`. handles are never reused, so a stale handle can't
+/// refer to a newer timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerHandle(u64);
+
+/// how often a scheduled timer fires before it is removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Once,
+    /// `Times(0)` fires once, just like `Once`
+    Times(u32),
+    Forever,
+}
+
+enum Action<E> {
+    Callback(Box<dyn FnMut()>),
+    Event(E),
+}
+
+struct Scheduled<E> {
+    timer: Timer,
+    repeat: Repeat,
+    fired: u32,
+    action: Action<E>,
+}
+
+/// owns any number of timers and advances them together. a timer either calls a callback or
+/// queues an event whenever it fires, events are collected with `drain_events`.
+pub struct TimerScheduler<E> {
+    timers: std::collections::BTreeMap<TimerHandle, Scheduled<E>>,
+    next_handle: u64,
+    events: Vec<E>,
+}
+
+impl<E: Clone> TimerScheduler<E> {
+    pub fn new() -> Self {
+        Self {
+            timers: std::collections::BTreeMap::new(),
+            next_handle: 0,
+            events: vec![],
+        }
+    }
+
+    /// calls `callback` every time `duration` elapses
+    pub fn schedule_callback(
+        &mut self,
+        duration: Duration,
+        repeat: Repeat,
+        callback: impl FnMut() + 'static,
+    ) -> TimerHandle {
+        self.schedule(duration, repeat, Action::Callback(Box::new(callback)))
+    }
+
+    /// queues `event` every time `duration` elapses
+    pub fn schedule_event(&mut self, duration: Duration, repeat: Repeat, event: E) -> TimerHandle {
+        self.schedule(duration, repeat, Action::Event(event))
+    }
+
+    /// returns whether the timer was still scheduled
+    pub fn cancel(&mut self, handle: TimerHandle) -> bool {
+        self.timers.remove(&handle).is_some()
+    }
+
+    pub fn cancel_all(&mut self) {
+        self.timers.clear()
+    }
+
+    pub fn is_scheduled(&self, handle: TimerHandle) -> bool {
+        self.timers.contains_key(&handle)
+    }
+
+    /// time until the timer fires next
+    pub fn remaining(&self, handle: TimerHandle) -> Option<Duration> {
+        self.timers
+            .get(&handle)
+            .map(|scheduled| scheduled.timer.remaining())
+    }
+
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// advances every timer, by `Timing::time_delta` to follow the game clock or by
+    /// `Timing::real_time_delta` to keep firing while the game is paused. firings are dispatched
+    /// in the order they became due within `time_delta`, a timer that wrapped several times fires
+    /// once per wrap. firings due at the same time keep the order the timers were scheduled in.
+    pub fn update(&mut self, time_delta: Duration) {
+        let mut firings = vec![];
+        for (handle, scheduled) in &mut self.timers {
+            let until_first = scheduled.timer.remaining();
+            let wraps = scheduled.timer.update(time_delta);
+            let left = match scheduled.repeat {
+                Repeat::Once => 1,
+                Repeat::Times(times) => times.max(1).saturating_sub(scheduled.fired),
+                Repeat::Forever => u32::MAX,
+            };
+            for wrap in 0..wraps.min(left) {
+                let due = scheduled
+                    .timer
+                    .duration()
+                    .checked_mul(wrap)
+                    .and_then(|offset| offset.checked_add(until_first))
+                    .unwrap_or(Duration::MAX);
+                firings.push((due, *handle));
+            }
+        }
+        // stable, so timers due at the same time stay in handle order
+        firings.sort_by_key(|(due, _)| *due);
+
+        for (_, handle) in firings {
+            let Some(scheduled) = self.timers.get_mut(&handle) else {
+                continue;
+            };
+            scheduled.fired += 1;
+            match &mut scheduled.action {
+                Action::Callback(callback) => callback(),
+                Action::Event(event) => self.events.push(event.clone()),
+            }
+            let done = match scheduled.repeat {
+                Repeat::Once => true,
+                Repeat::Times(times) => scheduled.fired >= times,
+                Repeat::Forever => false,
+            };
+            if done {
+                self.timers.remove(&handle);
+            }
+        }
+    }
+
+    /// events of fired timers in the order they fired in
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, E> {
+        self.events.drain(..)
+    }
+
+    fn schedule(&mut self, duration: Duration, repeat: Repeat, action: Action<E>) -> TimerHandle {
+        let handle = TimerHandle(self.next_handle);
+        self.next_handle += 1;
+        let mut timer = Timer::new(duration, repeat != Repeat::Once);
+        timer.start();
+        self.timers.insert(
+            handle,
+            Scheduled {
+                timer,
+                repeat,
+                fired: 0,
+                action,
+            },
+        );
+        handle
+    }
+}
+
+impl<E: Clone> Default for TimerScheduler<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+    use crate::test_util::ms;
+
+    fn started(duration: Duration, looping: bool) -> Timer {
+        let mut timer = Timer::new(duration, looping);
+        timer.start();
+        timer
+    }
+
+    #[test]
+    fn new_timer_is_stopped() {
+        let mut timer = Timer::new(ms(100), false);
+        assert!(!timer.started());
+        assert!(!timer.running());
+        assert_eq!(timer.update(ms(500)), 0);
+        assert!(!timer.finished());
+        assert_eq!(timer.remaining(), ms(100));
+        assert_eq!(timer.progress(), 0.0);
+    }
+
+    #[test]
+    fn started_timer_counts_down() {
+        let mut timer = started(ms(100), false);
+        assert!(timer.running());
+        assert_eq!(timer.update(ms(25)), 0);
+        assert_eq!(timer.elapsed(), ms(25));
+        assert_eq!(timer.remaining(), ms(75));
+        assert_eq!(timer.progress(), 0.25);
+        assert!(!timer.finished());
+    }
+
+    #[test]
+    fn one_shot_finishes_and_stays_finished() {
+        let mut timer = started(ms(100), false);
+        assert_eq!(timer.update(ms(150)), 1);
+        assert!(timer.finished());
+        assert_eq!(timer.times_finished(), 1);
+        assert_eq!(timer.remaining(), Duration::ZERO);
+        assert_eq!(timer.progress(), 1.0);
+        assert_eq!(timer.update(ms(150)), 0);
+        assert!(timer.finished());
+        assert_eq!(timer.times_finished(), 0);
+    }
+
+    #[test]
+    fn finishing_exactly_on_the_duration() {
+        let mut timer = started(ms(100), false);
+        assert_eq!(timer.update(ms(100)), 1);
+        assert!(timer.finished());
+    }
+
+    #[test]
+    fn looping_timer_carries_the_overshoot() {
+        let mut timer = started(ms(100), true);
+        assert_eq!(timer.update(ms(130)), 1);
+        assert!(timer.finished());
+        assert_eq!(timer.elapsed(), ms(30));
+        assert_eq!(timer.update(ms(10)), 0);
+        assert!(!timer.finished());
+        assert_eq!(timer.elapsed(), ms(40));
+    }
+
+    #[test]
+    fn looping_timer_reports_every_wrap() {
+        let mut timer = started(ms(100), true);
+        timer.update(ms(50));
+        assert_eq!(timer.update(ms(300)), 3);
+        assert_eq!(timer.times_finished(), 3);
+        assert_eq!(timer.elapsed(), ms(50));
+        assert_eq!(timer.update(ms(50)), 1);
+        assert_eq!(timer.remaining(), ms(100));
+    }
+
+    #[test]
+    fn zero_length_loop_wraps_once_per_update() {
+        let mut timer = started(Duration::ZERO, true);
+        assert_eq!(timer.update(ms(10)), 1);
+        assert_eq!(timer.update(Duration::ZERO), 1);
+        assert_eq!(timer.progress(), 1.0);
+    }
+
+    #[test]
+    fn paused_timer_keeps_its_progress() {
+        let mut timer = started(ms(100), false);
+        timer.update(ms(40));
+        timer.pause();
+        assert!(timer.paused());
+        assert!(timer.started());
+        assert!(!timer.running());
+        assert_eq!(timer.update(ms(100)), 0);
+        assert_eq!(timer.elapsed(), ms(40));
+        timer.resume();
+        assert!(timer.running());
+        assert_eq!(timer.update(ms(60)), 1);
+    }
+
+    #[test]
+    fn pausing_a_stopped_timer_does_nothing() {
+        let mut timer = Timer::new(ms(100), false);
+        timer.pause();
+        assert!(!timer.paused());
+        timer.start();
+        assert!(timer.running());
+    }
+
+    #[test]
+    fn start_resumes_a_paused_timer() {
+        let mut timer = started(ms(100), false);
+        timer.pause();
+        timer.start();
+        assert!(timer.running());
+    }
+
+    #[test]
+    fn stop_rewinds() {
+        let mut timer = started(ms(100), false);
+        timer.update(ms(150));
+        timer.stop();
+        assert!(!timer.started());
+        assert!(!timer.finished());
+        assert_eq!(timer.remaining(), ms(100));
+        assert_eq!(timer.update(ms(150)), 0);
+    }
+
+    #[test]
+    fn reset_rewinds_but_keeps_running() {
+        let mut timer = started(ms(100), false);
+        timer.update(ms(150));
+        timer.reset();
+        assert!(timer.started());
+        assert!(!timer.finished());
+        assert_eq!(timer.remaining(), ms(100));
+        assert_eq!(timer.update(ms(100)), 1);
+
+        let mut stopped = Timer::new(ms(100), true);
+        stopped.reset();
+        assert!(!stopped.started());
+    }
+
+    #[test]
+    fn scheduler_fires_once_per_wrap() {
+        let mut scheduler = TimerScheduler::new();
+        let handle = scheduler.schedule_event(ms(10), Repeat::Times(5), ());
+        scheduler.update(ms(35));
+        assert_eq!(scheduler.drain_events().count(), 3);
+        assert_eq!(scheduler.remaining(handle), Some(ms(5)));
+        scheduler.update(ms(100));
+        assert_eq!(scheduler.drain_events().count(), 2);
+        assert!(!scheduler.is_scheduled(handle));
+    }
+
+    #[test]
+    fn one_shot_callback_fires_once() {
+        let mut scheduler = TimerScheduler::<()>::new();
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let handle = scheduler.schedule_callback(ms(100), Repeat::Once, move || {
+            counter.set(counter.get() + 1)
+        });
+        scheduler.update(ms(60));
+        assert_eq!(calls.get(), 0);
+        assert_eq!(scheduler.remaining(handle), Some(ms(40)));
+        scheduler.update(ms(60));
+        assert_eq!(calls.get(), 1);
+        assert!(!scheduler.is_scheduled(handle));
+        scheduler.update(ms(200));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn repeating_events_are_queued_in_order() {
+        let mut scheduler = TimerScheduler::new();
+        let twice = scheduler.schedule_event(ms(10), Repeat::Times(2), "twice");
+        scheduler.schedule_event(ms(25), Repeat::Forever, "forever");
+        for _ in 0..6 {
+            scheduler.update(ms(11));
+        }
+        assert!(!scheduler.is_scheduled(twice));
+        assert_eq!(scheduler.len(), 1);
+        let events: Vec<_> = scheduler.drain_events().collect();
+        assert_eq!(events.iter().filter(|event| **event == "twice").count(), 2);
+        assert!(events.contains(&"forever"));
+        assert_eq!(scheduler.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn firings_are_interleaved_by_when_they_were_due() {
+        let mut scheduler = TimerScheduler::new();
+        scheduler.schedule_event(ms(10), Repeat::Forever, "fast");
+        scheduler.schedule_event(ms(25), Repeat::Once, "slow");
+        scheduler.schedule_event(ms(20), Repeat::Times(2), "twice");
+        scheduler.update(ms(45));
+        let events: Vec<_> = scheduler.drain_events().collect();
+        assert_eq!(
+            events,
+            ["fast", "fast", "twice", "slow", "fast", "fast", "twice"]
+        );
+    }
+
+    #[test]
+    fn repeat_count_limits_firings_within_one_update() {
+        let mut scheduler = TimerScheduler::new();
+        let handle = scheduler.schedule_event(ms(10), Repeat::Times(3), ());
+        scheduler.update(ms(100));
+        assert_eq!(scheduler.drain_events().count(), 3);
+        assert!(!scheduler.is_scheduled(handle));
+    }
+
+    #[test]
+    fn zero_times_fires_once() {
+        let mut scheduler = TimerScheduler::new();
+        let handle = scheduler.schedule_event(ms(10), Repeat::Times(0), ());
+        scheduler.update(ms(100));
+        assert_eq!(scheduler.drain_events().count(), 1);
+        assert!(!scheduler.is_scheduled(handle));
+    }
+
+    #[test]
+    fn cancelled_timers_never_fire() {
+        let mut scheduler = TimerScheduler::new();
+        let handle = scheduler.schedule_event(ms(10), Repeat::Forever, ());
+        assert!(scheduler.cancel(handle));
+        assert!(!scheduler.cancel(handle));
+        scheduler.update(ms(100));
+        assert_eq!(scheduler.drain_events().count(), 0);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn cancel_all_clears_the_scheduler() {
+        let mut scheduler = TimerScheduler::new();
+        scheduler.schedule_event(ms(10), Repeat::Forever, ());
+        scheduler.schedule_event(ms(20), Repeat::Once, ());
+        assert_eq!(scheduler.len(), 2);
+        scheduler.cancel_all();
+        assert!(scheduler.is_empty());
+        scheduler.update(ms(100));
+        assert_eq!(scheduler.drain_events().count(), 0);
+    }
 }
@@ -1,35 +1,534 @@
-use std::time::{Duration, Instant};
+use std::{collections::VecDeque, time::Duration};
 
+use crate::clock::{Clock, RealClock};
+
+/// frames kept by the rolling frame statistics of `Timing`
+const FRAME_WINDOW: usize = 1000;
+
+/// default simulation rate in steps per second
+const FIXED_RATE: f32 = 60.0;
+
+/// default cap on simulation steps per frame
+const MAX_FIXED_STEPS: u32 = 5;
+
+/// frame timing derived from a clock. the time delta, the time since start and the simulation
+/// steps follow the scaled game time, which stands still while paused. frame statistics always
+/// measure the unscaled time.
 pub struct Timing {
-    start_instant: Instant,
-    last_frame_instant: Instant,
+    clock: Box<dyn Clock>,
+    last_frame: Duration,
     time_delta: Duration,
+    real_time_delta: Duration,
+    time_since_start: Duration,
+    time_scale: f32,
+    paused: bool,
+    frame_stats: FrameStats,
+    fixed_timestep: FixedTimestep,
 }
 
 impl Timing {
     pub fn new() -> Self {
+        Self::with_clock(RealClock::new())
+    }
+
+    pub fn with_clock(clock: impl Clock + 'static) -> Self {
         Self {
-            start_instant: Instant::now(),
-            last_frame_instant: Instant::now(),
-            time_delta: Duration::from_secs(0),
+            last_frame: clock.now(),
+            clock: Box::new(clock),
+            time_delta: Duration::ZERO,
+            real_time_delta: Duration::ZERO,
+            time_since_start: Duration::ZERO,
+            time_scale: 1.0,
+            paused: false,
+            frame_stats: FrameStats::new(FRAME_WINDOW),
+            fixed_timestep: FixedTimestep::new(FIXED_RATE, MAX_FIXED_STEPS),
         }
     }
 
     pub fn update(&mut self) {
-        let now = Instant::now();
-        self.time_delta = now - self.last_frame_instant;
-        self.last_frame_instant = now;
+        let now = self.clock.now();
+        self.real_time_delta = now.saturating_sub(self.last_frame);
+        self.last_frame = now;
+        self.time_delta = if self.paused {
+            Duration::ZERO
+        } else {
+            self.real_time_delta.mul_f64(self.time_scale as f64)
+        };
+        self.time_since_start += self.time_delta;
+        self.frame_stats.push(self.real_time_delta);
+        self.fixed_timestep.advance(self.time_delta);
     }
 
+    /// scaled game time up to the last update
     pub fn time_since_start(&self) -> Duration {
-        Instant::now() - self.start_instant
+        self.time_since_start
     }
 
+    /// scaled time of the last frame, zero while paused
     pub fn time_delta(&self) -> Duration {
         self.time_delta
     }
 
+    /// time the last frame actually took
+    pub fn real_time_delta(&self) -> Duration {
+        self.real_time_delta
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// below 1.0 slows the game time down, above speeds it up. negative scales are clamped to
+    /// zero.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0)
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused
+    }
+
+    /// averaged over the rolling window, so single frames don't make it flicker
     pub fn fps(&self) -> u32 {
-        (1.0 / self.time_delta.as_secs_f32()) as u32
+        self.frame_stats.average_fps().unwrap_or(0.0) as u32
+    }
+
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    pub fn frame_stats_mut(&mut self) -> &mut FrameStats {
+        &mut self.frame_stats
+    }
+
+    /// simulation steps to run this frame
+    pub fn fixed_steps(&self) -> u32 {
+        self.fixed_timestep.steps()
+    }
+
+    /// time delta of a single simulation step
+    pub fn fixed_time_delta(&self) -> Duration {
+        self.fixed_timestep.time_step()
+    }
+
+    /// how far rendering is between the previous and the current simulation step
+    pub fn interpolation_alpha(&self) -> f32 {
+        self.fixed_timestep.alpha()
+    }
+
+    pub fn fixed_timestep_mut(&mut self) -> &mut FixedTimestep {
+        &mut self.fixed_timestep
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// accumulates frame time and splits it into simulation steps of a fixed length, so the
+/// simulation behaves the same at every frame rate
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    time_step: Duration,
+    max_steps: u32,
+    accumulator: Duration,
+    steps: u32,
+}
+
+impl FixedTimestep {
+    /// `rate` in steps per second. at most `max_steps` are run per frame, time beyond that is
+    /// dropped so slow frames can't snowball into ever more steps.
+    pub fn new(rate: f32, max_steps: u32) -> Self {
+        Self {
+            time_step: time_step(rate),
+            max_steps: max_steps.max(1),
+            accumulator: Duration::ZERO,
+            steps: 0,
+        }
+    }
+
+    pub fn rate(&self) -> f32 {
+        1.0 / self.time_step.as_secs_f32()
+    }
+
+    /// keeps the progress towards the next step, so the interpolation doesn't jump
+    pub fn set_rate(&mut self, rate: f32) {
+        let alpha = self.alpha() as f64;
+        self.time_step = time_step(rate);
+        self.accumulator = self.time_step.mul_f64(alpha)
+    }
+
+    pub fn max_steps(&self) -> u32 {
+        self.max_steps
+    }
+
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = max_steps.max(1)
+    }
+
+    pub fn time_step(&self) -> Duration {
+        self.time_step
+    }
+
+    /// steps produced by the last call to `advance`
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// fraction of a step left in the accumulator, in 0..1
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.time_step.as_secs_f64()) as f32
+    }
+
+    /// adds the frame time and returns the steps to run for it
+    pub fn advance(&mut self, time_delta: Duration) -> u32 {
+        self.accumulator += time_delta;
+        let steps = (self.accumulator.as_nanos() / self.time_step.as_nanos()) as u32;
+        self.steps = steps.min(self.max_steps);
+        if steps > self.max_steps {
+            tracing::debug!(
+                "dropping {} simulation steps to catch up",
+                steps - self.max_steps
+            );
+        }
+        self.accumulator -= self.time_step * steps;
+        self.steps
+    }
+}
+
+/// clamped to at least one step per second
+fn time_step(rate: f32) -> Duration {
+    Duration::from_secs_f64(1.0 / rate.max(1.0) as f64)
+}
+
+/// statistics over a rolling window of frame times. frames taking longer than the hitch
+/// threshold are logged.
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    frame_times: VecDeque<Duration>,
+    capacity: usize,
+    hitch_threshold: Duration,
+    hitch_count: u64,
+}
+
+impl FrameStats {
+    /// keeps the last `capacity` frames, with a hitch threshold of 50ms
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            frame_times: VecDeque::with_capacity(capacity),
+            capacity,
+            hitch_threshold: Duration::from_millis(50),
+            hitch_count: 0,
+        }
+    }
+
+    pub fn hitch_threshold(&self) -> Duration {
+        self.hitch_threshold
+    }
+
+    pub fn set_hitch_threshold(&mut self, hitch_threshold: Duration) {
+        self.hitch_threshold = hitch_threshold
+    }
+
+    /// adds a frame, dropping the oldest one if the window is full. returns whether the frame
+    /// was a hitch.
+    pub fn push(&mut self, frame_time: Duration) -> bool {
+        if self.frame_times.len() == self.capacity {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+
+        let hitch = frame_time > self.hitch_threshold;
+        if hitch {
+            self.hitch_count += 1;
+            tracing::warn!(
+                "hitch: frame took {:.2}ms, threshold is {:.2}ms",
+                frame_time.as_secs_f64() * 1000.0,
+                self.hitch_threshold.as_secs_f64() * 1000.0
+            );
+        }
+        hitch
+    }
+
+    pub fn clear(&mut self) {
+        self.frame_times.clear();
+        self.hitch_count = 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.frame_times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frame_times.is_empty()
+    }
+
+    /// hitches since creation or the last clear, including frames which left the window
+    pub fn hitch_count(&self) -> u64 {
+        self.hitch_count
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+        Some(self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32)
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.frame_times.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.frame_times.iter().max().copied()
+    }
+
+    pub fn average_fps(&self) -> Option<f32> {
+        self.average().map(fps)
+    }
+
+    /// nearest rank percentile of the frame times, `percentile` is clamped to 0..=100
+    pub fn percentile(&self, percentile: f32) -> Option<Duration> {
+        let sorted = self.sorted();
+        if sorted.is_empty() {
+            return None;
+        }
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * sorted.len() as f32).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+
+    /// average fps over the slowest `percent` of frames, at least one frame
+    pub fn low_fps(&self, percent: f32) -> Option<f32> {
+        let sorted = self.sorted();
+        if sorted.is_empty() {
+            return None;
+        }
+        let count = ((percent.clamp(0.0, 100.0) / 100.0 * sorted.len() as f32).ceil() as usize)
+            .clamp(1, sorted.len());
+        let slowest = &sorted[sorted.len() - count..];
+        Some(fps(slowest.iter().sum::<Duration>() / count as u32))
+    }
+
+    pub fn one_percent_low(&self) -> Option<f32> {
+        self.low_fps(1.0)
+    }
+
+    pub fn point_one_percent_low(&self) -> Option<f32> {
+        self.low_fps(0.1)
+    }
+
+    /// frame counts in buckets of `bucket_width`, starting at zero. the last bucket also counts
+    /// every longer frame.
+    pub fn histogram(&self, bucket_width: Duration, bucket_count: usize) -> Vec<u32> {
+        let mut buckets = vec![0; bucket_count];
+        if bucket_count == 0 || bucket_width.is_zero() {
+            return buckets;
+        }
+        for frame_time in &self.frame_times {
+            let bucket = (frame_time.as_nanos() / bucket_width.as_nanos()) as usize;
+            buckets[bucket.min(bucket_count - 1)] += 1;
+        }
+        buckets
+    }
+
+    fn sorted(&self) -> Vec<Duration> {
+        let mut sorted: Vec<_> = self.frame_times.iter().copied().collect();
+        sorted.sort();
+        sorted
+    }
+}
+
+fn fps(frame_time: Duration) -> f32 {
+    if frame_time.is_zero() {
+        return 0.0;
+    }
+    1.0 / frame_time.as_secs_f32()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::ManualClock, test_util::ms};
+
+    fn stats(frame_times: &[u64]) -> FrameStats {
+        let mut stats = FrameStats::new(frame_times.len());
+        for frame_time in frame_times {
+            stats.push(ms(*frame_time));
+        }
+        stats
+    }
+
+    #[test]
+    fn empty_window_has_no_statistics() {
+        let stats = FrameStats::new(10);
+        assert!(stats.is_empty());
+        assert_eq!(stats.average(), None);
+        assert_eq!(stats.min(), None);
+        assert_eq!(stats.max(), None);
+        assert_eq!(stats.percentile(50.0), None);
+        assert_eq!(stats.one_percent_low(), None);
+        assert_eq!(stats.histogram(ms(10), 3), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn average_min_and_max() {
+        let stats = stats(&[10, 20, 30, 40]);
+        assert_eq!(stats.average(), Some(ms(25)));
+        assert_eq!(stats.min(), Some(ms(10)));
+        assert_eq!(stats.max(), Some(ms(40)));
+        assert_eq!(stats.average_fps(), Some(40.0));
+    }
+
+    #[test]
+    fn window_drops_oldest_frames() {
+        let mut stats = FrameStats::new(3);
+        for frame_time in [100, 10, 20, 30] {
+            stats.push(ms(frame_time));
+        }
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats.max(), Some(ms(30)));
+        assert_eq!(stats.average(), Some(ms(20)));
+    }
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let stats = stats(&(1..=100).collect::<Vec<_>>());
+        assert_eq!(stats.percentile(0.0), Some(ms(1)));
+        assert_eq!(stats.percentile(50.0), Some(ms(50)));
+        assert_eq!(stats.percentile(99.0), Some(ms(99)));
+        assert_eq!(stats.percentile(100.0), Some(ms(100)));
+        assert_eq!(stats.percentile(250.0), Some(ms(100)));
+    }
+
+    #[test]
+    fn lows_average_the_slowest_frames() {
+        let mut frame_times = vec![10; 998];
+        frame_times.extend([50, 100]);
+        let stats = stats(&frame_times);
+        // slowest 10 frames: 8 * 10ms, 50ms and 100ms
+        assert_eq!(stats.one_percent_low(), Some(1.0 / 0.023));
+        // slowest frame only
+        assert_eq!(stats.point_one_percent_low(), Some(10.0));
+    }
+
+    #[test]
+    fn lows_cover_at_least_one_frame() {
+        let stats = stats(&[10, 20]);
+        assert_eq!(stats.point_one_percent_low(), Some(50.0));
+    }
+
+    #[test]
+    fn histogram_counts_overflow_in_last_bucket() {
+        let stats = stats(&[1, 9, 10, 15, 25, 100]);
+        assert_eq!(stats.histogram(ms(10), 3), vec![2, 2, 2]);
+        assert_eq!(stats.histogram(Duration::ZERO, 2), vec![0, 0]);
+    }
+
+    #[test]
+    fn frames_over_the_threshold_are_hitches() {
+        let mut stats = FrameStats::new(2);
+        stats.set_hitch_threshold(ms(20));
+        assert_eq!(stats.hitch_threshold(), ms(20));
+        assert!(!stats.push(ms(20)));
+        assert!(stats.push(ms(21)));
+        assert!(stats.push(ms(100)));
+        assert!(!stats.push(ms(5)));
+        assert_eq!(stats.hitch_count(), 2);
+        stats.clear();
+        assert_eq!(stats.hitch_count(), 0);
+        assert!(stats.is_empty());
+    }
+
+    #[test]
+    fn fixed_timestep_accumulates_partial_steps() {
+        let mut timestep = FixedTimestep::new(100.0, 5);
+        assert_eq!(timestep.advance(ms(5)), 0);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(timestep.advance(ms(25)), 3);
+        assert_eq!(timestep.steps(), 3);
+        assert!(timestep.alpha().abs() < 1e-6);
+    }
+
+    #[test]
+    fn fixed_timestep_caps_catch_up_steps() {
+        let mut timestep = FixedTimestep::new(100.0, 4);
+        assert_eq!(timestep.advance(ms(1005)), 4);
+        // the dropped steps don't carry over, only the partial step does
+        assert!((timestep.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(timestep.advance(ms(10)), 1);
+
+        timestep.set_max_steps(0);
+        assert_eq!(timestep.max_steps(), 1);
+        assert_eq!(timestep.advance(ms(50)), 1);
+    }
+
+    #[test]
+    fn fixed_timestep_keeps_its_progress_when_the_rate_changes() {
+        let mut timestep = FixedTimestep::new(100.0, 5);
+        timestep.advance(ms(7));
+        timestep.set_rate(50.0);
+        assert_eq!(timestep.time_step(), ms(20));
+        assert!((timestep.alpha() - 0.7).abs() < 1e-6);
+        assert_eq!(timestep.advance(ms(6)), 1);
+
+        timestep.advance(ms(18));
+        timestep.set_rate(200.0);
+        assert!(timestep.alpha() < 1.0);
+        assert!((timestep.alpha() - 0.9).abs() < 1e-6);
+        assert_eq!(timestep.advance(ms(1)), 1);
+    }
+
+    #[test]
+    fn timing_follows_the_clock() {
+        let clock = ManualClock::new();
+        let mut timing = Timing::with_clock(clock.clone());
+        clock.advance(ms(16));
+        timing.update();
+        assert_eq!(timing.time_delta(), ms(16));
+        assert_eq!(timing.real_time_delta(), ms(16));
+        clock.advance(ms(44));
+        timing.update();
+        assert_eq!(timing.time_delta(), ms(44));
+        assert_eq!(timing.time_since_start(), ms(60));
+        assert_eq!(timing.fixed_steps(), 3);
+        assert_eq!(timing.frame_stats().len(), 2);
+    }
+
+    #[test]
+    fn time_scale_changes_game_time_only() {
+        let clock = ManualClock::new();
+        let mut timing = Timing::with_clock(clock.clone());
+        timing.set_time_scale(0.5);
+        clock.advance(ms(20));
+        timing.update();
+        assert_eq!(timing.time_delta(), ms(10));
+        assert_eq!(timing.real_time_delta(), ms(20));
+        assert_eq!(timing.frame_stats().max(), Some(ms(20)));
+        timing.set_time_scale(-1.0);
+        assert_eq!(timing.time_scale(), 0.0);
+    }
+
+    #[test]
+    fn paused_timing_stands_still() {
+        let clock = ManualClock::new();
+        let mut timing = Timing::with_clock(clock.clone());
+        timing.set_paused(true);
+        clock.advance(ms(100));
+        timing.update();
+        assert_eq!(timing.time_delta(), Duration::ZERO);
+        assert_eq!(timing.time_since_start(), Duration::ZERO);
+        assert_eq!(timing.fixed_steps(), 0);
+        timing.set_paused(false);
+        clock.advance(ms(20));
+        timing.update();
+        assert_eq!(timing.time_delta(), ms(20));
+        assert_eq!(timing.time_since_start(), ms(20));
     }
 }
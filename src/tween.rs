@@ -0,0 +1,619 @@
+use std::{f32::consts::PI, time::Duration};
+
+use cgmath::{Quaternion, Vector2, Vector3, Vector4, VectorSpace};
+
+use crate::timer::{Repeat, Timer};
+
+/// a value that can be blended between two end points. `t` can leave [0, 1] for easings that
+/// overshoot, like elastic.
+pub trait Tweenable: Clone {
+    fn tween(&self, to: &Self, t: f32) -> Self;
+}
+
+impl Tweenable for f32 {
+    fn tween(&self, to: &Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Tweenable for Vector2<f32> {
+    fn tween(&self, to: &Self, t: f32) -> Self {
+        self.lerp(*to, t)
+    }
+}
+
+impl Tweenable for Vector3<f32> {
+    fn tween(&self, to: &Self, t: f32) -> Self {
+        self.lerp(*to, t)
+    }
+}
+
+impl Tweenable for Vector4<f32> {
+    fn tween(&self, to: &Self, t: f32) -> Self {
+        self.lerp(*to, t)
+    }
+}
+
+/// rotates along the shortest arc
+impl Tweenable for Quaternion<f32> {
+    fn tween(&self, to: &Self, t: f32) -> Self {
+        self.slerp(*to, t)
+    }
+}
+
+impl Tweenable for wgpu::Color {
+    fn tween(&self, to: &Self, t: f32) -> Self {
+        let t = t as f64;
+        wgpu::Color {
+            r: self.r + (to.r - self.r) * t,
+            g: self.g + (to.g - self.g) * t,
+            b: self.b + (to.b - self.b) * t,
+            a: self.a + (to.a - self.a) * t,
+        }
+    }
+}
+
+/// maps the linear progress of a tween to the eased one, both usually in [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+    /// the control points of a css `cubic-bezier(x1, y1, x2, y2)`, x1 and x2 have to be in
+    /// [0, 1]
+    CubicBezier(f32, f32, f32, f32),
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match *self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::ElasticIn => 1.0 - elastic_out(1.0 - t),
+            Easing::ElasticOut => elastic_out(t),
+            Easing::ElasticInOut => {
+                if t < 0.5 {
+                    (1.0 - elastic_out(1.0 - 2.0 * t)) / 2.0
+                } else {
+                    (1.0 + elastic_out(2.0 * t - 1.0)) / 2.0
+                }
+            }
+            Easing::BounceIn => 1.0 - bounce_out(1.0 - t),
+            Easing::BounceOut => bounce_out(t),
+            Easing::BounceInOut => {
+                if t < 0.5 {
+                    (1.0 - bounce_out(1.0 - 2.0 * t)) / 2.0
+                } else {
+                    (1.0 + bounce_out(2.0 * t - 1.0)) / 2.0
+                }
+            }
+            Easing::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+        }
+    }
+}
+
+fn elastic_out(t: f32) -> f32 {
+    if t <= 0.0 || t >= 1.0 {
+        return t;
+    }
+    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+}
+
+fn bounce_out(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+/// finds the curve parameter whose x is `t` and returns its y. newton's method converges in a
+/// few steps for most curves, bisection catches the flat ones.
+fn cubic_bezier(x1: f32, y1: f32, x2: f32, y2: f32, t: f32) -> f32 {
+    let curve = |a: f32, b: f32, s: f32| {
+        let inverse = 1.0 - s;
+        3.0 * inverse * inverse * s * a + 3.0 * inverse * s * s * b + s * s * s
+    };
+    let slope = |a: f32, b: f32, s: f32| {
+        let inverse = 1.0 - s;
+        3.0 * inverse * inverse * a + 6.0 * inverse * s * (b - a) + 3.0 * s * s * (1.0 - b)
+    };
+
+    let mut s = t;
+    for _ in 0..8 {
+        let error = curve(x1, x2, s) - t;
+        if error.abs() < 1e-6 {
+            return curve(y1, y2, s);
+        }
+        let derivative = slope(x1, x2, s);
+        if derivative.abs() < 1e-6 {
+            break;
+        }
+        s -= error / derivative;
+    }
+
+    let (mut low, mut high) = (0.0, 1.0);
+    s = t;
+    for _ in 0..32 {
+        let x = curve(x1, x2, s);
+        if (x - t).abs() < 1e-6 {
+            break;
+        }
+        if x < t {
+            low = s
+        } else {
+            high = s
+        }
+        s = (low + high) / 2.0
+    }
+    curve(y1, y2, s)
+}
+
+/// something that plays over time. `update` returns the part of `time_delta` left over after
+/// it finished, so a sequence can hand it on to the next animation without losing time.
+pub trait Animation {
+    fn update(&mut self, time_delta: Duration) -> Duration;
+    fn finished(&self) -> bool;
+    /// rewinds to the beginning
+    fn reset(&mut self);
+}
+
+impl<A: Animation + ?Sized> Animation for Box<A> {
+    fn update(&mut self, time_delta: Duration) -> Duration {
+        (**self).update(time_delta)
+    }
+
+    fn finished(&self) -> bool {
+        (**self).finished()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+/// blends from one value to another with an easing curve, optionally looping and playing every
+/// other loop backwards. it is advanced with the same delta as everything else, either
+/// `Timing::time_delta` per frame or `Timing::fixed_time_delta` per fixed step.
+#[derive(Debug, Clone)]
+pub struct Tween<T: Tweenable> {
+    from: T,
+    to: T,
+    easing: Easing,
+    timer: Timer,
+    repeat: Repeat,
+    yoyo: bool,
+    loops: u32,
+    finished: bool,
+}
+
+impl<T: Tweenable> Tween<T> {
+    pub fn new(from: T, to: T, duration: Duration) -> Self {
+        let mut timer = Timer::new(duration, false);
+        timer.start();
+        Self {
+            from,
+            to,
+            easing: Easing::Linear,
+            timer,
+            repeat: Repeat::Once,
+            yoyo: false,
+            loops: 0,
+            finished: false,
+        }
+    }
+
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// how often the tween plays, a yoyo counts each direction as one play
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self.timer.set_looping(repeat != Repeat::Once);
+        self
+    }
+
+    /// plays every other loop from `to` back to `from`
+    pub fn yoyo(mut self, yoyo: bool) -> Self {
+        self.yoyo = yoyo;
+        self
+    }
+
+    pub fn value(&self) -> T {
+        let (index, t) = if self.finished {
+            (self.loops.saturating_sub(1), 1.0)
+        } else {
+            (self.loops, self.timer.progress())
+        };
+        let t = if self.yoyo && index % 2 == 1 {
+            1.0 - t
+        } else {
+            t
+        };
+        self.from.tween(&self.to, self.easing.apply(t))
+    }
+
+    pub fn from(&self) -> &T {
+        &self.from
+    }
+
+    pub fn to(&self) -> &T {
+        &self.to
+    }
+
+    /// restarts from `from` towards a new target, e.g. when a camera move gets redirected
+    pub fn retarget(&mut self, from: T, to: T) {
+        self.from = from;
+        self.to = to;
+        self.reset()
+    }
+
+    pub fn pause(&mut self) {
+        self.timer.pause()
+    }
+
+    pub fn resume(&mut self) {
+        self.timer.resume()
+    }
+
+    pub fn paused(&self) -> bool {
+        self.timer.paused()
+    }
+
+    /// progress of the current loop in [0, 1], before easing
+    pub fn progress(&self) -> f32 {
+        if self.finished {
+            1.0
+        } else {
+            self.timer.progress()
+        }
+    }
+
+    fn total_loops(&self) -> Option<u32> {
+        match self.repeat {
+            Repeat::Once => Some(1),
+            Repeat::Times(times) => Some(times.max(1)),
+            Repeat::Forever => None,
+        }
+    }
+}
+
+impl<T: Tweenable> Animation for Tween<T> {
+    fn update(&mut self, time_delta: Duration) -> Duration {
+        if self.finished {
+            return time_delta;
+        }
+        if self.paused() {
+            return Duration::ZERO;
+        }
+        if let Some(total) = self.total_loops() {
+            let loops_left = total - self.loops;
+            // an end too far away to be represented can't be reached by this update either
+            let until_end = self
+                .timer
+                .duration()
+                .checked_mul(loops_left - 1)
+                .and_then(|rest| rest.checked_add(self.timer.remaining()));
+            if let Some(until_end) = until_end.filter(|&until_end| time_delta >= until_end) {
+                self.loops = total;
+                self.finished = true;
+                return time_delta - until_end;
+            }
+        }
+        self.loops = self.loops.saturating_add(self.timer.update(time_delta));
+        Duration::ZERO
+    }
+
+    fn finished(&self) -> bool {
+        self.finished
+    }
+
+    fn reset(&mut self) {
+        self.timer.reset();
+        self.loops = 0;
+        self.finished = false
+    }
+}
+
+/// plays animations one after another, time left over by one carries into the next
+#[derive(Debug, Clone)]
+pub struct Sequence<A: Animation> {
+    animations: Vec<A>,
+    current: usize,
+}
+
+impl<A: Animation> Sequence<A> {
+    pub fn new() -> Self {
+        Self {
+            animations: vec![],
+            current: 0,
+        }
+    }
+
+    pub fn then(mut self, animation: A) -> Self {
+        self.animations.push(animation);
+        self
+    }
+
+    pub fn push(&mut self, animation: A) {
+        self.animations.push(animation)
+    }
+
+    /// the animation currently playing, or the last one once finished
+    pub fn current(&self) -> Option<&A> {
+        self.animations
+            .get(self.current)
+            .or_else(|| self.animations.last())
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current.min(self.animations.len().saturating_sub(1))
+    }
+
+    pub fn animations(&self) -> &[A] {
+        &self.animations
+    }
+}
+
+impl<A: Animation> Default for Sequence<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Tweenable> Sequence<Tween<T>> {
+    /// value of the current tween
+    pub fn value(&self) -> Option<T> {
+        self.current().map(Tween::value)
+    }
+}
+
+impl<A: Animation> Animation for Sequence<A> {
+    fn update(&mut self, mut time_delta: Duration) -> Duration {
+        while let Some(animation) = self.animations.get_mut(self.current) {
+            time_delta = animation.update(time_delta);
+            if !animation.finished() {
+                return Duration::ZERO;
+            }
+            self.current += 1;
+        }
+        time_delta
+    }
+
+    fn finished(&self) -> bool {
+        self.current >= self.animations.len()
+    }
+
+    fn reset(&mut self) {
+        self.animations.iter_mut().for_each(Animation::reset);
+        self.current = 0
+    }
+}
+
+/// plays animations at the same time, finished once all of them are. mixing value types works
+/// with `Parallel<Box<dyn Animation>>`.
+#[derive(Debug, Clone)]
+pub struct Parallel<A: Animation> {
+    animations: Vec<A>,
+}
+
+impl<A: Animation> Parallel<A> {
+    pub fn new() -> Self {
+        Self { animations: vec![] }
+    }
+
+    pub fn with(mut self, animation: A) -> Self {
+        self.animations.push(animation);
+        self
+    }
+
+    pub fn push(&mut self, animation: A) {
+        self.animations.push(animation)
+    }
+
+    pub fn animations(&self) -> &[A] {
+        &self.animations
+    }
+}
+
+impl<A: Animation> Default for Parallel<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Tweenable> Parallel<Tween<T>> {
+    pub fn values(&self) -> Vec<T> {
+        self.animations.iter().map(Tween::value).collect()
+    }
+}
+
+impl<A: Animation> Animation for Parallel<A> {
+    fn update(&mut self, time_delta: Duration) -> Duration {
+        self.animations
+            .iter_mut()
+            .map(|animation| animation.update(time_delta))
+            .min()
+            .unwrap_or(time_delta)
+    }
+
+    fn finished(&self) -> bool {
+        self.animations.iter().all(Animation::finished)
+    }
+
+    fn reset(&mut self) {
+        self.animations.iter_mut().for_each(Animation::reset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{assert_close, ms};
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        for easing in [
+            Easing::Linear,
+            Easing::QuadIn,
+            Easing::QuadOut,
+            Easing::QuadInOut,
+            Easing::CubicIn,
+            Easing::CubicOut,
+            Easing::CubicInOut,
+            Easing::ElasticIn,
+            Easing::ElasticOut,
+            Easing::ElasticInOut,
+            Easing::BounceIn,
+            Easing::BounceOut,
+            Easing::BounceInOut,
+            Easing::CubicBezier(0.25, 0.1, 0.25, 1.0),
+        ] {
+            assert_close(easing.apply(0.0), 0.0);
+            assert_close(easing.apply(1.0), 1.0);
+        }
+        assert_close(Easing::QuadIn.apply(0.5), 0.25);
+        assert_close(Easing::CubicInOut.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn linear_cubic_bezier_is_linear() {
+        let easing = Easing::CubicBezier(1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0);
+        for t in [0.1, 0.25, 0.5, 0.9] {
+            assert_close(easing.apply(t), t)
+        }
+    }
+
+    #[test]
+    fn tween_follows_its_easing() {
+        let mut tween = Tween::new(10.0, 20.0, ms(100)).easing(Easing::QuadIn);
+        assert_eq!(tween.update(ms(50)), Duration::ZERO);
+        assert_close(tween.value(), 12.5);
+        assert_eq!(tween.update(ms(80)), ms(30));
+        assert!(tween.finished());
+        assert_close(tween.value(), 20.0);
+    }
+
+    #[test]
+    fn yoyo_plays_back_and_forth() {
+        let mut tween = Tween::new(Vector2::new(0.0, 0.0), Vector2::new(1.0, 2.0), ms(100))
+            .repeat(Repeat::Times(2))
+            .yoyo(true);
+        tween.update(ms(150));
+        assert_close(tween.value().y, 1.0);
+        tween.update(ms(40));
+        assert_close(tween.value().x, 0.1);
+        tween.update(ms(20));
+        assert!(tween.finished());
+        assert_eq!(tween.value(), Vector2::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn paused_tween_keeps_its_value() {
+        let mut tween = Tween::new(0.0, 1.0, ms(100));
+        tween.update(ms(40));
+        tween.pause();
+        assert!(tween.paused());
+        assert_eq!(tween.update(ms(100)), Duration::ZERO);
+        assert_close(tween.progress(), 0.4);
+        tween.resume();
+        assert_eq!(tween.update(ms(100)), ms(40));
+        assert_close(tween.progress(), 1.0);
+    }
+
+    #[test]
+    fn retarget_restarts_towards_the_new_target() {
+        let mut tween = Tween::new(0.0, 1.0, ms(100));
+        tween.update(ms(150));
+        assert!(tween.finished());
+        tween.retarget(tween.value(), 3.0);
+        assert!(!tween.finished());
+        assert_eq!((*tween.from(), *tween.to()), (1.0, 3.0));
+        tween.update(ms(50));
+        assert_close(tween.value(), 2.0);
+    }
+
+    #[test]
+    fn looping_tween_never_finishes() {
+        let mut tween = Tween::new(0.0, 1.0, ms(100)).repeat(Repeat::Forever);
+        tween.update(ms(1030));
+        assert!(!tween.finished());
+        assert_close(tween.value(), 0.3);
+    }
+
+    #[test]
+    fn repeating_a_long_tween_many_times_does_not_overflow() {
+        let mut tween =
+            Tween::new(0.0, 1.0, Duration::from_secs(u64::MAX / 2)).repeat(Repeat::Times(u32::MAX));
+        assert_eq!(tween.update(ms(100)), Duration::ZERO);
+        assert!(!tween.finished());
+    }
+
+    #[test]
+    fn sequence_carries_left_over_time() {
+        let mut sequence = Sequence::new()
+            .then(Tween::new(0.0, 1.0, ms(100)))
+            .then(Tween::new(1.0, 3.0, ms(100)));
+        sequence.update(ms(150));
+        assert_eq!(sequence.current_index(), 1);
+        assert_close(sequence.value().unwrap(), 2.0);
+        assert_eq!(sequence.update(ms(100)), ms(50));
+        assert!(sequence.finished());
+        sequence.reset();
+        assert_close(sequence.value().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn parallel_finishes_with_the_longest() {
+        let mut parallel = Parallel::<Box<dyn Animation>>::new()
+            .with(Box::new(Tween::new(0.0, 1.0, ms(50))))
+            .with(Box::new(Tween::new(
+                wgpu::Color::BLACK,
+                wgpu::Color::WHITE,
+                ms(100),
+            )));
+        assert_eq!(parallel.update(ms(60)), Duration::ZERO);
+        assert!(!parallel.finished());
+        assert_eq!(parallel.update(ms(60)), ms(20));
+        assert!(parallel.finished());
+    }
+}